@@ -0,0 +1,133 @@
+/*
+ * The kernel console: a small command line that is typed into over the
+ * serial port (e.g. "-serial stdio" in QEMU) and answers on the serial port
+ * and on the screen. Everything that is typed is echoed to both as well.
+ */
+use crate::kmsg;
+use crate::logger::{self, Sink};
+use crate::vga_buffer::{self, TextMode};
+use crate::{print, serial, serial_print};
+use log::LevelFilter;
+
+const MAX_LINE: usize = 78;
+const PROMPT: &str = "> ";
+
+// Say something on the screen and on the serial port
+macro_rules! say {
+    ($($arg:tt)*) => {{
+        print!($($arg)*);
+        serial_print!($($arg)*);
+    }};
+}
+
+macro_rules! sayln {
+    () => (say!("\n"));
+    ($($arg:tt)*) => (say!("{}\n", format_args!($($arg)*)));
+}
+
+// Read and run commands, forever. Needs interrupts to be on
+pub fn run() -> ! {
+    let mut line = [0u8; MAX_LINE];
+    let mut len = 0;
+    say!("{}", PROMPT);
+    for byte in serial::Input {
+        match byte {
+            // Terminals send either of them for the enter key
+            b'\r' | b'\n' => {
+                sayln!();
+                // Only printable ASCII gets into the line
+                let command = core::str::from_utf8(&line[..len]).unwrap_or("");
+                execute(command.trim());
+                len = 0;
+                say!("{}", PROMPT);
+            }
+            // Backspace or delete, depending on the terminal
+            0x08 | 0x7f if len > 0 => {
+                len -= 1;
+                print!("\x08");
+                // Terminals only move the cursor back, we have to erase
+                serial_print!("\x08 \x08");
+            }
+            b' '..=b'~' if len < MAX_LINE => {
+                line[len] = byte;
+                len += 1;
+                say!("{}", byte as char);
+            }
+            _ => {}
+        }
+    }
+    unreachable!("the serial input never ends");
+}
+
+fn execute(command: &str) {
+    let (name, argument) = split_word(command);
+    match name {
+        "" => {}
+        "help" => {
+            sayln!("help            this list");
+            sayln!("echo <text>     say text");
+            sayln!("clear           clear the screen");
+            sayln!("console <n>     show virtual console n");
+            sayln!("mode <mode>     80x25, 80x50, 90x30 or 90x60");
+            sayln!("dmesg           show everything that was printed or logged");
+            sayln!("loglevel [lvl]  show or set off/error/warn/info/debug/trace");
+            sayln!("logsink <s> <b> turn vga, serial or memory on or off");
+            sayln!("panic           panic on purpose");
+        }
+        "echo" => sayln!("{}", argument),
+        "clear" => {
+            print!("\x0c");
+            serial_print!("\x1b[2J\x1b[H");
+        }
+        "console" => match argument.parse::<usize>() {
+            Ok(index) if index < vga_buffer::NUM_CONSOLES => vga_buffer::switch_console(index),
+            _ => sayln!("there are consoles 0 to {}", vga_buffer::NUM_CONSOLES - 1),
+        },
+        "mode" => {
+            let mode = match argument {
+                "80x25" => TextMode::Text80x25,
+                "80x50" => TextMode::Text80x50,
+                "90x30" => TextMode::Text90x30,
+                "90x60" => TextMode::Text90x60,
+                _ => return sayln!("unknown mode '{}'", argument),
+            };
+            vga_buffer::set_text_mode(mode);
+        }
+        "dmesg" => {
+            for record in kmsg::records() {
+                sayln!("{}", record);
+            }
+        }
+        "loglevel" if argument.is_empty() => sayln!("{}", logger::level()),
+        "loglevel" => match argument.parse::<LevelFilter>() {
+            Ok(level) => logger::set_level(level),
+            Err(_) => sayln!("unknown level '{}'", argument),
+        },
+        "logsink" => set_sink(argument),
+        "panic" => panic!("asked for it on the console"),
+        _ => sayln!("unknown command '{}', try help", name),
+    }
+}
+
+fn set_sink(argument: &str) {
+    let (name, state) = split_word(argument);
+    let sink = match name {
+        "vga" => Sink::Vga,
+        "serial" => Sink::Serial,
+        "memory" => Sink::Memory,
+        _ => return sayln!("unknown sink '{}'", name),
+    };
+    match state {
+        "on" => logger::set_sink(sink, true),
+        "off" => logger::set_sink(sink, false),
+        _ => sayln!("on or off?"),
+    }
+}
+
+// The first word and the rest
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(' ') {
+        Some(space) => (&text[..space], text[space + 1..].trim()),
+        None => (text, ""),
+    }
+}
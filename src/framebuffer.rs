@@ -0,0 +1,334 @@
+/*
+ * A text console for linear framebuffers, the kind of screen UEFI firmware
+ * (and bootloaders that set up a VESA mode) hand over. There is no text mode
+ * and no 0xb8000 there, just a block of memory with a color for every pixel,
+ * so we draw every character ourselves from the bitmaps of a PSF font.
+ *
+ * The console understands the same text as vga_buffer::Writer: code page 437
+ * characters, the same control characters and the color escape sequences,
+ * and it uses the same 16 colors. Once init() was called, print! and
+ * println! write here instead of to the VGA.
+ *
+ * Nobody calls init() yet: bootloader 0.8 starts us in VGA text mode and has
+ * no framebuffer to hand over. It is there for a boot path that does, which
+ * passes what it got in the boot information on to init().
+ */
+use crate::vga_buffer::{self, ansi, cp437, Color, ColorCode, TAB_WIDTH};
+use crate::{psf, speaker};
+use core::fmt;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+static FONT_8X16: &[u8] = include_bytes!("../fonts/misc-fixed-8x16.psf");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    // Red in the first byte of a pixel, then green and blue
+    Rgb,
+    // Blue first, what most firmware uses
+    Bgr,
+}
+
+// Where the framebuffer is and what it looks like, the bootloader knows this
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBufferInfo {
+    pub address: usize,
+    // In pixels
+    pub width: usize,
+    pub height: usize,
+    // Bytes from the start of one row of pixels to the next one
+    pub stride: usize,
+    // 3 or 4, the 4th byte is unused
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
+}
+
+pub struct FrameBufferWriter {
+    info: FrameBufferInfo,
+    font: psf::Font<'static>,
+    // The size of the screen in characters
+    columns: usize,
+    rows: usize,
+    column_position: usize,
+    row_position: usize,
+    color_code: ColorCode,
+    default_color: ColorCode,
+    parser: ansi::Parser,
+    // Called for "\x07", like Writer's bell
+    bell: Option<fn()>,
+    // What the 16 colors look like, see vga_buffer::set_palette()
+    palette: [[u8; 3]; 16],
+}
+
+impl FrameBufferWriter {
+    /*
+     * Unsafe because we have to trust info: everything it describes gets
+     * written to.
+     */
+    pub unsafe fn new(info: FrameBufferInfo, palette: [[u8; 3]; 16]) -> FrameBufferWriter {
+        assert!(
+            info.bytes_per_pixel == 3 || info.bytes_per_pixel == 4,
+            "unsupported framebuffer with {} bytes per pixel",
+            info.bytes_per_pixel
+        );
+        let font = psf::Font::parse(FONT_8X16).expect("broken 8x16 font");
+        let color_code = ColorCode::new(Color::Yellow, Color::Black);
+        let mut writer = FrameBufferWriter {
+            info,
+            font,
+            columns: info.width / font.width(),
+            rows: info.height / font.height(),
+            column_position: 0,
+            row_position: 0,
+            color_code,
+            default_color: color_code,
+            parser: ansi::Parser::new(),
+            bell: Some(speaker::beep),
+            palette,
+        };
+        writer.clear();
+        writer
+    }
+
+    // In characters
+    pub fn width(&self) -> usize {
+        self.columns
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows
+    }
+
+    fn write_pixel(&mut self, x: usize, y: usize, color: u8) {
+        let [red, green, blue] = self.palette[color as usize & 0x0f];
+        let bytes = match self.info.format {
+            PixelFormat::Rgb => [red, green, blue],
+            PixelFormat::Bgr => [blue, green, red],
+        };
+        let offset = y * self.info.stride + x * self.info.bytes_per_pixel;
+        let pixel = (self.info.address + offset) as *mut u8;
+        for (index, &byte) in bytes.iter().enumerate() {
+            unsafe { pixel.add(index).write_volatile(byte) };
+        }
+    }
+
+    // Fill a rectangle of the screen, in pixels
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.write_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn draw_glyph(&mut self, row: usize, col: usize, byte: u8) {
+        let (width, height) = (self.font.width(), self.font.height());
+        let glyph = self.font.glyph(byte as usize).unwrap_or(&[]);
+        let bytes_per_row = width.div_ceil(8);
+        for y in 0..height {
+            for x in 0..width {
+                let bits = glyph.get(y * bytes_per_row + x / 8).copied().unwrap_or(0);
+                let color = if bits & (0x80 >> (x % 8)) != 0 {
+                    self.color_code.foreground()
+                } else {
+                    self.color_code.background()
+                };
+                self.write_pixel(col * width + x, row * height + y, color);
+            }
+        }
+    }
+
+    // A code page 437 character, no matter if it is a control character
+    pub fn write_glyph(&mut self, byte: u8) {
+        if self.column_position >= self.columns {
+            self.new_line();
+        }
+        self.draw_glyph(self.row_position, self.column_position, byte);
+        self.column_position += 1;
+    }
+
+    /*
+     * The control characters do what Writer::write_byte() does with them,
+     * there just is no cursor to move.
+     */
+    pub fn write_byte(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.column_position = 0,
+            b'\t' => {
+                let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+                self.column_position = next_stop.min(self.columns);
+            }
+            // Backspace, the character it goes back over is erased
+            0x08 => {
+                if self.column_position > 0 {
+                    let col = self.column_position.min(self.columns) - 1;
+                    self.clear_cell(self.row_position, col);
+                    self.column_position = col;
+                }
+            }
+            // Form feed, a new page
+            0x0c => self.clear(),
+            0x07 => {
+                if let Some(bell) = self.bell {
+                    bell();
+                }
+            }
+            byte => self.write_glyph(byte),
+        }
+    }
+
+    // Only for what is drawn from now on, the pixels on the screen stay as they are
+    pub fn set_palette(&mut self, palette: [[u8; 3]; 16]) {
+        self.palette = palette;
+    }
+
+    // See Writer::set_bell()
+    pub fn set_bell(&mut self, bell: Option<fn()>) {
+        self.bell = bell;
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        for c in s.chars() {
+            match self.parser.advance(c) {
+                ansi::Action::None => {}
+                ansi::Action::Csi(sequence) => self.execute_csi(&sequence),
+                ansi::Action::Print(c @ ('\n' | '\r' | '\t' | '\x08' | '\x0c' | '\x07')) => {
+                    self.write_byte(c as u8)
+                }
+                ansi::Action::Print(c) => self.write_glyph(cp437::from_char(c).unwrap_or(0xfe)),
+            }
+        }
+    }
+
+    /*
+     * Only the colors and erasing the screen, we have no cursor to move
+     * around.
+     */
+    fn execute_csi(&mut self, sequence: &ansi::CsiSequence) {
+        match sequence.command {
+            b'm' => {
+                self.color_code = self
+                    .color_code
+                    .select_graphic_rendition(sequence.params(), self.default_color)
+            }
+            b'J' if sequence.param_or(0, 0) == 2 => self.clear(),
+            _ => {}
+        }
+    }
+
+    /*
+     * Copying pixels around is a lot of memory traffic compared to the text
+     * mode, but a framebuffer can't scroll by itself.
+     */
+    fn new_line(&mut self) {
+        self.column_position = 0;
+        if self.row_position < self.rows - 1 {
+            self.row_position += 1;
+            return;
+        }
+        let row_bytes = self.font.height() * self.info.stride;
+        let screen = self.info.address as *mut u8;
+        unsafe {
+            core::ptr::copy(screen.add(row_bytes), screen, (self.rows - 1) * row_bytes);
+        }
+        self.clear_row(self.rows - 1);
+    }
+
+    fn clear_cell(&mut self, row: usize, col: usize) {
+        let (width, height) = (self.font.width(), self.font.height());
+        self.fill(
+            col * width,
+            row * height,
+            width,
+            height,
+            self.color_code.background(),
+        );
+    }
+
+    fn clear_row(&mut self, row: usize) {
+        let height = self.font.height();
+        let width = self.columns * self.font.width();
+        self.fill(0, row * height, width, height, self.color_code.background());
+    }
+
+    // The whole framebuffer, also the border the characters don't fill
+    pub fn clear(&mut self) {
+        let (width, height) = (self.info.width, self.info.height);
+        self.fill(0, 0, width, height, self.color_code.background());
+        self.row_position = 0;
+        self.column_position = 0;
+    }
+
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    pub fn reset_color(&mut self) {
+        self.color_code = self.default_color;
+    }
+
+    pub fn with_color<F>(&mut self, foreground: Color, background: Color, f: F)
+    where
+        F: FnOnce(&mut FrameBufferWriter),
+    {
+        let color_code = self.color_code;
+        self.set_color(foreground, background);
+        f(self);
+        self.color_code = color_code;
+    }
+}
+
+impl fmt::Write for FrameBufferWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
+
+// The framebuffer console, None as long as we are using the VGA
+pub static FRAMEBUFFER: Mutex<Option<FrameBufferWriter>> = Mutex::new(None);
+
+/*
+ * Send print! and println! to the framebuffer from now on.
+ * Unsafe for the same reason FrameBufferWriter::new() is.
+ */
+pub unsafe fn init(info: FrameBufferInfo) {
+    let writer = FrameBufferWriter::new(info, vga_buffer::palette());
+    interrupts::without_interrupts(|| *FRAMEBUFFER.lock() = Some(writer));
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    // 80x25 characters of 8x16 pixels, in memory that lives as long as the test
+    fn writer() -> FrameBufferWriter {
+        let (width, height) = (640, 400);
+        let memory = Box::leak(vec![0u8; width * height * 4].into_boxed_slice());
+        let info = FrameBufferInfo {
+            address: memory.as_mut_ptr() as usize,
+            width,
+            height,
+            stride: width * 4,
+            bytes_per_pixel: 4,
+            format: PixelFormat::Bgr,
+        };
+        let mut writer = unsafe { FrameBufferWriter::new(info, vga_buffer::DEFAULT_PALETTE) };
+        writer.set_bell(None);
+        writer
+    }
+
+    #[test]
+    fn control_characters_are_not_drawn() {
+        let mut writer = writer();
+        writer.write_string("abc\x08\x08");
+        assert_eq!(writer.column_position, 1);
+        writer.write_string("\tx\r");
+        assert_eq!(writer.column_position, 0);
+        writer.write_string("\n\n\x0c");
+        assert_eq!((writer.row_position, writer.column_position), (0, 0));
+        writer.write_string("\x07");
+        assert_eq!(writer.column_position, 0);
+    }
+}
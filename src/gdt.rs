@@ -0,0 +1,104 @@
+/*
+ * The global descriptor table (GDT) and the task state segment (TSS).
+ *
+ * In 64-bit mode segmentation is mostly gone, but the CPU still wants a GDT:
+ * the code segment says whether we run in 64-bit mode and with which
+ * privilege level, kernel (ring 0) or user (ring 3). The bootloader left us
+ * one of its own, which we replace with ours. There is a code and a data
+ * segment for the kernel and for user mode, user data first because that is
+ * the order syscall/sysret expect.
+ *
+ * The TSS doesn't switch tasks anymore, it holds stacks. Its interrupt stack
+ * table (IST) has seven known-good stacks an interrupt handler can ask for
+ * in its IDT entry. The CPU switches to that stack before it pushes anything,
+ * so the handler runs even when the stack of the interrupted code is gone.
+ * That is what a kernel stack overflow does: it runs into the guard page
+ * below the stack, the page fault can't push its stack frame and turns into
+ * a double fault. On the same broken stack the double fault would turn into
+ * a triple fault, which resets the machine. On a stack of its own it gets to
+ * tell us what happened.
+ */
+use core::ptr::addr_of;
+use lazy_static::lazy_static;
+use x86_64::instructions::segmentation::{Segment, CS, DS, ES, SS};
+use x86_64::instructions::tables::load_tss;
+use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::VirtAddr;
+
+// Where in the IST the stacks of the handlers are, see exceptions.rs
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+// NMIs and machine checks can come at any time, even while the stack is bad
+pub const NON_MASKABLE_INTERRUPT_IST_INDEX: u16 = 1;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 2;
+
+// Enough for a handler that prints a report and panics
+const IST_STACK_SIZE: usize = 4096 * 5;
+
+/*
+ * The stacks are plain arrays, we have no memory management yet. They are
+ * static mut because the CPU writes to them, but only the CPU ever does.
+ * Stacks grow down, so the IST has to point at the end.
+ */
+static mut DOUBLE_FAULT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut NON_MASKABLE_INTERRUPT_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+static mut MACHINE_CHECK_STACK: [u8; IST_STACK_SIZE] = [0; IST_STACK_SIZE];
+
+fn stack_end(stack: *const [u8; IST_STACK_SIZE]) -> VirtAddr {
+    VirtAddr::from_ptr(stack) + IST_STACK_SIZE
+}
+
+lazy_static! {
+    static ref TSS: TaskStateSegment = {
+        let mut tss = TaskStateSegment::new();
+        tss.interrupt_stack_table[usize::from(DOUBLE_FAULT_IST_INDEX)] =
+            stack_end(addr_of!(DOUBLE_FAULT_STACK));
+        tss.interrupt_stack_table[usize::from(NON_MASKABLE_INTERRUPT_IST_INDEX)] =
+            stack_end(addr_of!(NON_MASKABLE_INTERRUPT_STACK));
+        tss.interrupt_stack_table[usize::from(MACHINE_CHECK_IST_INDEX)] =
+            stack_end(addr_of!(MACHINE_CHECK_STACK));
+        tss
+    };
+}
+
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+lazy_static! {
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let mut gdt = GlobalDescriptorTable::new();
+        let selectors = Selectors {
+            kernel_code: gdt.add_entry(Descriptor::kernel_code_segment()),
+            kernel_data: gdt.add_entry(Descriptor::kernel_data_segment()),
+            user_data: gdt.add_entry(Descriptor::user_data_segment()),
+            user_code: gdt.add_entry(Descriptor::user_code_segment()),
+            tss: gdt.add_entry(Descriptor::tss_segment(&TSS)),
+        };
+        (gdt, selectors)
+    };
+}
+
+/*
+ * Load the GDT and the TSS. The segment registers still hold selectors into
+ * the GDT of the bootloader, they have to be reloaded with ours.
+ */
+pub fn init() {
+    let (gdt, selectors) = &*GDT;
+    gdt.load();
+    unsafe {
+        CS::set_reg(selectors.kernel_code);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        SS::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
+    }
+}
+
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
+}
@@ -0,0 +1,138 @@
+/*
+ * Hardware interrupts. The devices don't talk to the CPU directly, they raise
+ * an IRQ line of the 8259 programmable interrupt controller (PIC). There are
+ * two of them chained together, 8 lines each, and the second one is hooked
+ * up to line 2 of the first.
+ *
+ * The PIC turns an IRQ into an interrupt vector, and the CPU looks up the
+ * handler for that vector in the interrupt descriptor table (IDT). Out of the
+ * box the PIC uses vectors 8 to 15, which clash with the CPU exceptions, so we
+ * move them to 32 onwards, right after the exceptions.
+ *
+ * The CPU exceptions have their handlers in the same IDT, see exceptions.rs.
+ */
+use crate::serial;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::lazy_static;
+use pic8259::ChainedPics;
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+mod exceptions;
+
+pub const PIC_1_OFFSET: u8 = 32;
+pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
+
+pub static PICS: Mutex<ChainedPics> =
+    Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
+
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum InterruptIndex {
+    // IRQ 0, the programmable interval timer
+    Timer = PIC_1_OFFSET,
+    // IRQ 4, the first serial port
+    Com1 = PIC_1_OFFSET + 4,
+}
+
+impl InterruptIndex {
+    fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    fn as_usize(self) -> usize {
+        usize::from(self.as_u8())
+    }
+
+    // The bit of the IRQ line in the mask of the first PIC
+    fn pic_1_mask(self) -> u8 {
+        1 << (self.as_u8() - PIC_1_OFFSET)
+    }
+}
+
+// IRQ 2 is where the second PIC is connected
+const CASCADE_MASK: u8 = 1 << 2;
+
+/*
+ * The programmable interval timer counts down from 65536 at 1193182 Hz,
+ * unless someone tells it otherwise, and raises IRQ 0 each time it gets to 0.
+ */
+pub const PIT_FREQUENCY: u64 = 1_193_182;
+const PIT_DIVISOR: u64 = 65_536;
+
+// Timer interrupts since interrupts were turned on
+static TICKS: AtomicU64 = AtomicU64::new(0);
+// Run by every timer interrupt once it is set, see set_timer_hook()
+static TIMER_HOOK: Once<fn()> = Once::new();
+
+lazy_static! {
+    static ref IDT: InterruptDescriptorTable = {
+        let mut idt = InterruptDescriptorTable::new();
+        exceptions::install(&mut idt);
+        idt[InterruptIndex::Timer.as_usize()].set_handler_fn(timer_interrupt_handler);
+        idt[InterruptIndex::Com1.as_usize()].set_handler_fn(com1_interrupt_handler);
+        idt
+    };
+}
+
+/*
+ * Load the IDT, set up the PICs and turn interrupts on. Only the IRQs we have
+ * a handler for are let through, a masked IRQ never reaches the CPU.
+ */
+pub fn init() {
+    IDT.load();
+    let enabled = InterruptIndex::Timer.pic_1_mask() | InterruptIndex::Com1.pic_1_mask();
+    unsafe {
+        let mut pics = PICS.lock();
+        pics.initialize();
+        pics.write_masks(!(enabled | CASCADE_MASK), 0xff);
+    }
+    serial::enable_input();
+    interrupts::enable();
+}
+
+/*
+ * Milliseconds since interrupts were turned on, in steps of the ~55ms
+ * between two timer interrupts. Before that it is always 0.
+ */
+pub fn uptime_ms() -> u64 {
+    TICKS.load(Ordering::Relaxed) * PIT_DIVISOR * 1000 / PIT_FREQUENCY
+}
+
+/*
+ * Have hook run by every timer interrupt from now on. It runs in interrupt
+ * context, so it must not take a lock that the code it interrupts could be
+ * holding. There is only one hook, calls after the first one are ignored.
+ * The test runner sets it to catch tests that hang, the kernel itself
+ * doesn't need one.
+ */
+pub fn set_timer_hook(hook: fn()) {
+    TIMER_HOOK.call_once(|| hook);
+}
+
+/*
+ * Every handler has to tell the PIC that it is done, until then the PIC
+ * doesn't send any interrupts of the same or a lower priority.
+ */
+fn end_of_interrupt(index: InterruptIndex) {
+    unsafe {
+        PICS.lock().notify_end_of_interrupt(index.as_u8());
+    }
+}
+
+// The timer fires ~18 times a second
+extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    crate::speaker::tick();
+    // Not there while set_timer_hook() is busy setting it
+    if let Some(hook) = TIMER_HOOK.r#try() {
+        hook();
+    }
+    end_of_interrupt(InterruptIndex::Timer);
+}
+
+extern "x86-interrupt" fn com1_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    serial::handle_interrupt();
+    end_of_interrupt(InterruptIndex::Com1);
+}
@@ -0,0 +1,208 @@
+/*
+ * CPU exceptions, the first 32 interrupt vectors. The CPU raises them itself
+ * when an instruction can't go on, e.g. on a division by zero, an opcode it
+ * doesn't know or a page that isn't mapped. Without a handler the CPU raises
+ * a double fault instead, and without a handler for that a triple fault,
+ * which resets the machine.
+ *
+ * Every handler says which exception it was, the error code if the exception
+ * has one, and where the CPU was when it happened (the interrupt stack
+ * frame). Then it depends on the exception:
+ * - traps are raised after the instruction, returning goes on with the next
+ *   one. That's what int3 (breakpoint) is for. The interrupted code goes on
+ *   as well, so we can't take its locks: what a trap says goes to the screen
+ *   if print! is free and straight to the serial port if it isn't.
+ * - faults are raised before it, returning would run into the same fault
+ *   again, so we panic. So do aborts, after them there is nothing to return
+ *   to.
+ */
+use crate::{gdt, serial, vga_buffer};
+use core::fmt;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+
+pub fn install(idt: &mut InterruptDescriptorTable) {
+    idt.divide_error.set_handler_fn(divide_error_handler);
+    idt.debug.set_handler_fn(debug_handler);
+    /*
+     * These three get a stack of their own in the TSS, see gdt.rs. Unsafe
+     * because the stack has to be there and no other handler may use it.
+     */
+    unsafe {
+        idt.non_maskable_interrupt
+            .set_handler_fn(non_maskable_interrupt_handler)
+            .set_stack_index(gdt::NON_MASKABLE_INTERRUPT_IST_INDEX);
+        idt.double_fault
+            .set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+        idt.machine_check
+            .set_handler_fn(machine_check_handler)
+            .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+    }
+    idt.breakpoint.set_handler_fn(breakpoint_handler);
+    idt.overflow.set_handler_fn(overflow_handler);
+    idt.bound_range_exceeded
+        .set_handler_fn(bound_range_exceeded_handler);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode_handler);
+    idt.device_not_available
+        .set_handler_fn(device_not_available_handler);
+    idt.invalid_tss.set_handler_fn(invalid_tss_handler);
+    idt.segment_not_present
+        .set_handler_fn(segment_not_present_handler);
+    idt.stack_segment_fault
+        .set_handler_fn(stack_segment_fault_handler);
+    idt.general_protection_fault
+        .set_handler_fn(general_protection_fault_handler);
+    idt.page_fault.set_handler_fn(page_fault_handler);
+    idt.x87_floating_point
+        .set_handler_fn(x87_floating_point_handler);
+    idt.alignment_check.set_handler_fn(alignment_check_handler);
+    idt.simd_floating_point
+        .set_handler_fn(simd_floating_point_handler);
+    idt.virtualization.set_handler_fn(virtualization_handler);
+    idt.cp_protection_exception
+        .set_handler_fn(cp_protection_handler);
+    idt.hv_injection_exception
+        .set_handler_fn(hv_injection_handler);
+    idt.vmm_communication_exception
+        .set_handler_fn(vmm_communication_handler);
+    idt.security_exception.set_handler_fn(security_handler);
+}
+
+/*
+ * The report goes through print, which is print! itself for fatal() and
+ * print_or_send() for the traps. details are whatever else there is to say
+ * about the exception, e.g. the address of a page fault.
+ */
+fn report(
+    print: fn(fmt::Arguments),
+    name: &str,
+    error_code: Option<u64>,
+    details: Option<fmt::Arguments>,
+    stack_frame: &InterruptStackFrame,
+) {
+    print(format_args!("EXCEPTION: {}\n", name));
+    if let Some(error_code) = error_code {
+        print(format_args!("error code: {:#x}\n", error_code));
+    }
+    if let Some(details) = details {
+        print(format_args!("{}\n", details));
+    }
+    print(format_args!("{:#?}\n", stack_frame));
+}
+
+// Never waits for a lock, see vga_buffer::try_print()
+fn print_or_send(args: fmt::Arguments) {
+    if !vga_buffer::try_print(args) {
+        serial::print_unlocked(args);
+    }
+}
+
+/*
+ * The code that was interrupted is never going to run again, so whatever
+ * locks it held are ours now. The full report goes into the kernel message
+ * ring as well, which the panic sends over the serial port.
+ */
+fn fatal(
+    name: &str,
+    error_code: Option<u64>,
+    details: Option<fmt::Arguments>,
+    stack_frame: &InterruptStackFrame,
+) -> ! {
+    unsafe {
+        crate::force_unlock();
+    }
+    report(vga_buffer::_print, name, error_code, details, stack_frame);
+    panic!(
+        "EXCEPTION: {} at {:#x}",
+        name,
+        stack_frame.instruction_pointer.as_u64()
+    );
+}
+
+// An exception we can return from, the interrupted code just goes on
+macro_rules! trap_handler {
+    ($handler:ident, $name:literal) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            report(print_or_send, $name, None, None, &stack_frame);
+        }
+    };
+}
+
+// One that would only happen again, with or without an error code
+macro_rules! fault_handler {
+    ($handler:ident, $name:literal) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame) {
+            fatal($name, None, None, &stack_frame);
+        }
+    };
+    ($handler:ident, $name:literal, error_code) => {
+        extern "x86-interrupt" fn $handler(stack_frame: InterruptStackFrame, error_code: u64) {
+            fatal($name, Some(error_code), None, &stack_frame);
+        }
+    };
+}
+
+trap_handler!(debug_handler, "DEBUG");
+// Usually a hardware failure, but there is nothing to undo
+trap_handler!(non_maskable_interrupt_handler, "NON-MASKABLE INTERRUPT");
+trap_handler!(breakpoint_handler, "BREAKPOINT");
+trap_handler!(overflow_handler, "OVERFLOW");
+
+fault_handler!(divide_error_handler, "DIVIDE ERROR");
+fault_handler!(bound_range_exceeded_handler, "BOUND RANGE EXCEEDED");
+fault_handler!(invalid_opcode_handler, "INVALID OPCODE");
+fault_handler!(device_not_available_handler, "DEVICE NOT AVAILABLE");
+fault_handler!(invalid_tss_handler, "INVALID TSS", error_code);
+fault_handler!(
+    segment_not_present_handler,
+    "SEGMENT NOT PRESENT",
+    error_code
+);
+fault_handler!(
+    stack_segment_fault_handler,
+    "STACK SEGMENT FAULT",
+    error_code
+);
+fault_handler!(
+    general_protection_fault_handler,
+    "GENERAL PROTECTION FAULT",
+    error_code
+);
+fault_handler!(x87_floating_point_handler, "X87 FLOATING POINT");
+fault_handler!(alignment_check_handler, "ALIGNMENT CHECK", error_code);
+fault_handler!(simd_floating_point_handler, "SIMD FLOATING POINT");
+fault_handler!(virtualization_handler, "VIRTUALIZATION");
+fault_handler!(cp_protection_handler, "CONTROL PROTECTION", error_code);
+fault_handler!(hv_injection_handler, "HYPERVISOR INJECTION");
+fault_handler!(vmm_communication_handler, "VMM COMMUNICATION", error_code);
+fault_handler!(security_handler, "SECURITY", error_code);
+
+// CR2 has the address that couldn't be accessed
+extern "x86-interrupt" fn page_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: PageFaultErrorCode,
+) {
+    fatal(
+        "PAGE FAULT",
+        Some(error_code.bits()),
+        Some(format_args!(
+            "accessed address: {:?}\n{:?}",
+            Cr2::read(),
+            error_code
+        )),
+        &stack_frame,
+    );
+}
+
+// An abort, the error code is always 0
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) -> ! {
+    fatal("DOUBLE FAULT", Some(error_code), None, &stack_frame);
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    fatal("MACHINE CHECK", None, None, &stack_frame);
+}
@@ -0,0 +1,307 @@
+/*
+ * The kernel message ring, what dmesg shows on Linux. Everything that goes
+ * through print! is also kept here, a record per line, each with a sequence
+ * number and the time since boot. Unlike the screen it doesn't scroll away
+ * and it is there from the very first print!, so it can be read later with
+ * the dmesg console command, and it is sent over the serial port on a panic.
+ *
+ * The ring has a fixed number of records of a fixed size, once it is full
+ * each new line takes the place of the oldest one. Sequence numbers keep
+ * counting, so a gap tells how much was lost. Colors and other escape
+ * sequences don't end up in here, only the text.
+ *
+ * The time comes from the timer interrupt, see interrupts::uptime_ms(). It
+ * doesn't tick before interrupts::init(), so everything printed before that
+ * is stamped 0.
+ */
+use core::fmt::{self, Write};
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+
+pub const KMSG_RECORDS: usize = 128;
+pub const RECORD_LENGTH: usize = 120;
+
+#[derive(Clone, Copy)]
+pub struct Record {
+    sequence: u64,
+    // Milliseconds since boot
+    timestamp: u64,
+    length: usize,
+    text: [u8; RECORD_LENGTH],
+}
+
+impl Record {
+    const EMPTY: Record = Record {
+        sequence: 0,
+        timestamp: 0,
+        length: 0,
+        text: [0; RECORD_LENGTH],
+    };
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn text(&self) -> &str {
+        // Only whole characters are ever copied in
+        core::str::from_utf8(&self.text[..self.length]).unwrap_or("")
+    }
+
+    fn push(&mut self, c: char) {
+        let mut encoded = [0; 4];
+        let encoded = c.encode_utf8(&mut encoded).as_bytes();
+        let end = self.length + encoded.len();
+        // Whatever doesn't fit is dropped, the record stays a single line
+        if end <= RECORD_LENGTH {
+            self.text[self.length..end].copy_from_slice(encoded);
+            self.length = end;
+        }
+    }
+}
+
+// Like dmesg: "[    1.234] text"
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", Timestamp(self.timestamp), self.text())
+    }
+}
+
+// Milliseconds since boot the way dmesg shows them, e.g. "[    1.250]"
+pub struct Timestamp(pub u64);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{:>5}.{:03}]", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+pub struct Kmsg {
+    records: [Record; KMSG_RECORDS],
+    // The sequence number of the next record, also how many there were
+    next_sequence: u64,
+    // The last record didn't get its newline yet, text is added to it
+    open: bool,
+    // In the middle of an escape sequence, which is left out
+    escape: bool,
+}
+
+impl Kmsg {
+    pub const fn new() -> Kmsg {
+        Kmsg {
+            records: [Record::EMPTY; KMSG_RECORDS],
+            next_sequence: 0,
+            open: false,
+            escape: false,
+        }
+    }
+
+    fn slot(sequence: u64) -> usize {
+        (sequence % KMSG_RECORDS as u64) as usize
+    }
+
+    // The oldest record that is still in the ring
+    pub fn first_sequence(&self) -> u64 {
+        self.next_sequence.saturating_sub(KMSG_RECORDS as u64)
+    }
+
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    pub fn get(&self, sequence: u64) -> Option<&Record> {
+        if sequence < self.first_sequence() || sequence >= self.next_sequence {
+            return None;
+        }
+        Some(&self.records[Self::slot(sequence)])
+    }
+
+    // Add text that was printed timestamp milliseconds after boot
+    pub fn write(&mut self, timestamp: u64, s: &str) {
+        for c in s.chars() {
+            if self.escape {
+                // CSI sequences end with a letter
+                self.escape = !c.is_ascii_alphabetic();
+                continue;
+            }
+            match c {
+                '\x1b' => self.escape = true,
+                '\n' => {
+                    // An empty line is a record too
+                    self.current(timestamp);
+                    self.open = false;
+                }
+                '\t' => self.current(timestamp).push(' '),
+                c if c.is_control() => {}
+                c => self.current(timestamp).push(c),
+            }
+        }
+    }
+
+    // The record that text goes into, a new one if the last line is done
+    fn current(&mut self, timestamp: u64) -> &mut Record {
+        if !self.open {
+            self.records[Self::slot(self.next_sequence)] = Record {
+                sequence: self.next_sequence,
+                timestamp,
+                ..Record::EMPTY
+            };
+            self.next_sequence += 1;
+            self.open = true;
+        }
+        &mut self.records[Self::slot(self.next_sequence - 1)]
+    }
+}
+
+impl Default for Kmsg {
+    fn default() -> Kmsg {
+        Kmsg::new()
+    }
+}
+
+static KMSG: Mutex<Kmsg> = Mutex::new(Kmsg::new());
+
+// Writes into the ring with the time of the print!
+struct KmsgWriter<'a> {
+    kmsg: &'a mut Kmsg,
+    timestamp: u64,
+}
+
+impl fmt::Write for KmsgWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.kmsg.write(self.timestamp, s);
+        Ok(())
+    }
+}
+
+// Called by print!, with interrupts off already
+#[doc(hidden)]
+pub fn _record(args: fmt::Arguments) {
+    let mut kmsg = KMSG.lock();
+    let mut writer = KmsgWriter {
+        kmsg: &mut kmsg,
+        timestamp: crate::interrupts::uptime_ms(),
+    };
+    let _ = writer.write_fmt(args);
+}
+
+// Keep text in the ring without showing it, e.g. for the logger's memory sink
+pub fn record(args: fmt::Arguments) {
+    interrupts::without_interrupts(|| _record(args));
+}
+
+// record() if KMSG is free, false if it isn't. Never waits, see vga_buffer::try_print()
+pub fn try_record(args: fmt::Arguments) -> bool {
+    interrupts::without_interrupts(|| {
+        let mut kmsg = match KMSG.try_lock() {
+            Some(kmsg) => kmsg,
+            None => return false,
+        };
+        let mut writer = KmsgWriter {
+            kmsg: &mut kmsg,
+            timestamp: crate::interrupts::uptime_ms(),
+        };
+        let _ = writer.write_fmt(args);
+        true
+    })
+}
+
+/*
+ * All records that are in the ring right now, oldest first. The ring is
+ * only locked while a record is copied out, so it is fine to print! while
+ * reading. What is printed in the meantime isn't read any more, otherwise
+ * dmesg would never finish.
+ */
+pub struct Reader {
+    next: u64,
+    end: u64,
+}
+
+pub fn records() -> Reader {
+    interrupts::without_interrupts(|| {
+        let kmsg = KMSG.lock();
+        Reader {
+            next: kmsg.first_sequence(),
+            end: kmsg.next_sequence(),
+        }
+    })
+}
+
+impl Iterator for Reader {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        while self.next < self.end {
+            let sequence = self.next;
+            self.next += 1;
+            // None if it was overwritten while we were reading
+            let record = interrupts::without_interrupts(|| KMSG.lock().get(sequence).copied());
+            if record.is_some() {
+                return record;
+            }
+        }
+        None
+    }
+}
+
+/*
+ * Part of crate::force_unlock(). Breaks KMSG, which every print! takes first,
+ * so that the panic message is recorded too and the panic screen can dump
+ * the log. A record that was being written is cut short, it is always valid
+ * UTF-8 though, see Record::push().
+ */
+pub unsafe fn force_unlock() {
+    KMSG.force_unlock();
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    fn texts(kmsg: &Kmsg) -> Vec<String> {
+        (kmsg.first_sequence()..kmsg.next_sequence())
+            .map(|sequence| kmsg.get(sequence).unwrap().text().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn a_record_per_line() {
+        let mut kmsg = Kmsg::new();
+        kmsg.write(10, "Hello ");
+        kmsg.write(20, "World!\n\nmore");
+        assert_eq!(texts(&kmsg), ["Hello World!", "", "more"]);
+        // A record has the time of its first character
+        assert_eq!(kmsg.get(0).unwrap().timestamp(), 10);
+        assert_eq!(kmsg.get(2).unwrap().timestamp(), 20);
+        assert_eq!(kmsg.get(2).unwrap().sequence(), 2);
+    }
+
+    #[test]
+    fn leaves_out_escape_sequences() {
+        let mut kmsg = Kmsg::new();
+        kmsg.write(0, "\x1b[97;41m\x1b[2J\x1b[H KERNEL PANIC\x07\n");
+        assert_eq!(texts(&kmsg), [" KERNEL PANIC"]);
+    }
+
+    #[test]
+    fn old_records_are_overwritten() {
+        let mut kmsg = Kmsg::new();
+        for number in 0..KMSG_RECORDS + 5 {
+            kmsg.write(0, &format!("line {}\n", number));
+        }
+        assert_eq!(kmsg.first_sequence(), 5);
+        assert!(kmsg.get(4).is_none());
+        assert_eq!(kmsg.get(5).unwrap().text(), "line 5");
+        assert_eq!(texts(&kmsg).len(), KMSG_RECORDS);
+    }
+
+    #[test]
+    fn formats_like_dmesg() {
+        let mut kmsg = Kmsg::new();
+        kmsg.write(61_234, "up\n");
+        assert_eq!(kmsg.get(0).unwrap().to_string(), "[   61.234] up");
+    }
+}
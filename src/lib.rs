@@ -0,0 +1,89 @@
+/*
+ * The kernel as a library. main.rs is only the entry point, this way the
+ * kernel tests in tests/ can boot the very same kernel, see testing.rs.
+ *
+ * There are two kinds of tests. The unit tests in the modules run on the
+ * host (cargo test-host, see .cargo/config), as a normal program with the
+ * standard library and its test harness. cargo test runs the kernel tests:
+ * every test binary is a kernel of its own that boots in QEMU, runs its
+ * #[test_case] functions and tells QEMU how it went.
+ */
+#![cfg_attr(any(not(test), target_os = "none"), no_std)]
+#![cfg_attr(all(test, target_os = "none"), no_main)]
+#![feature(abi_x86_interrupt)]
+#![cfg_attr(target_os = "none", feature(custom_test_frameworks))]
+#![cfg_attr(target_os = "none", test_runner(crate::testing::test_runner))]
+#![cfg_attr(target_os = "none", reexport_test_harness_main = "test_main")]
+// Why an unsafe fn is unsafe is in the comment above it, we have no doc comments
+#![allow(clippy::missing_safety_doc)]
+
+pub mod console;
+pub mod framebuffer;
+pub mod gdt;
+pub mod interrupts;
+pub mod kmsg;
+pub mod logger;
+pub mod panic_screen;
+mod psf;
+pub mod qemu;
+pub mod serial;
+pub mod speaker;
+pub mod testing;
+pub mod vga_buffer;
+
+use x86_64::instructions::{hlt, interrupts as cpu_interrupts};
+
+/*
+ * Everything the rest of the kernel needs, in the order it needs it. Nothing
+ * lazy_static is left to be built later, after this the exception handlers
+ * only use what is there already.
+ */
+pub fn init() {
+    vga_buffer::init();
+    serial::init();
+    logger::init();
+    // The IDT refers to the stacks in the TSS, loading them builds them
+    gdt::init();
+    interrupts::init();
+}
+
+/*
+ * Stop the CPU for good. With interrupts off nothing wakes it up again,
+ * except for an NMI. That's why hlt sits in a loop.
+ */
+pub fn halt() -> ! {
+    cpu_interrupts::disable();
+    loop {
+        hlt();
+    }
+}
+
+/*
+ * For panic handlers only, and the one place to call from there: the
+ * force_unlock() of each module only breaks that module's locks, this one
+ * breaks them all. Nothing that held a lock when the kernel panicked is
+ * ever going to release it, so we break all the locks that printing needs.
+ *
+ * Unsafe because whoever held a lock may still be in the middle of using
+ * it, only call this when that code will never run again.
+ */
+pub unsafe fn force_unlock() {
+    vga_buffer::force_unlock();
+    serial::force_unlock();
+    kmsg::force_unlock();
+}
+
+// cargo test on the kernel target boots the library on its own
+#[cfg(all(test, target_os = "none"))]
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[cfg(all(test, target_os = "none"))]
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    testing::test_panic_handler(info)
+}
@@ -0,0 +1,124 @@
+/*
+ * The kernel log. Code logs through the macros of the log crate, e.g.
+ * log::warn!("no framebuffer, using text mode"), and we decide what to do
+ * with it: every record that gets through the filter goes to each sink that
+ * is switched on.
+ *
+ * There are two filters. Records below the compile-time maximum (the
+ * max_level_* features of log in Cargo.toml) aren't even compiled in. The
+ * runtime one, set_level(), can only let through less than that.
+ *
+ * The sinks are the screen, in a color for each level, the serial port and
+ * the kernel message ring (kmsg.rs), which is still there when the screen
+ * has long scrolled on. The screen sink goes past the ring, so that a line
+ * only ends up in there once, and only if the memory sink is on. Each line
+ * starts with the time since boot, like the records of the ring do.
+ */
+use crate::kmsg::{self, Timestamp};
+use crate::vga_buffer::{self, Color};
+use crate::{interrupts, serial_print};
+use core::sync::atomic::{AtomicBool, Ordering};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+// What is logged until someone calls set_level()
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sink {
+    Vga,
+    Serial,
+    Memory,
+}
+
+static VGA_ENABLED: AtomicBool = AtomicBool::new(true);
+static SERIAL_ENABLED: AtomicBool = AtomicBool::new(true);
+static MEMORY_ENABLED: AtomicBool = AtomicBool::new(true);
+
+struct KernelLogger;
+
+static LOGGER: KernelLogger = KernelLogger;
+
+impl Sink {
+    fn enabled(self) -> &'static AtomicBool {
+        match self {
+            Sink::Vga => &VGA_ENABLED,
+            Sink::Serial => &SERIAL_ENABLED,
+            Sink::Memory => &MEMORY_ENABLED,
+        }
+    }
+}
+
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::LightRed,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::White,
+        Level::Debug => Color::LightCyan,
+        Level::Trace => Color::DarkGray,
+    }
+}
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = record.level();
+        let target = record.target();
+        let args = record.args();
+        // The same time kmsg puts in front of its records
+        let time = Timestamp(interrupts::uptime_ms());
+
+        if Sink::Vga.enabled().load(Ordering::Relaxed) {
+            // In one go, so that nothing an interrupt prints ends up in the middle
+            x86_64::instructions::interrupts::without_interrupts(|| {
+                vga_buffer::show(format_args!("{} ", time));
+                // Only the level is in color, the message is easier to read without
+                vga_buffer::show_colored(
+                    level_color(level),
+                    Color::Black,
+                    format_args!("[{:>5}]", level),
+                );
+                vga_buffer::show(format_args!(" {}: {}\n", target, args));
+            });
+        }
+        if Sink::Serial.enabled().load(Ordering::Relaxed) {
+            serial_print!("{} [{:>5}] {}: {}\n", time, level, target, args);
+        }
+        // kmsg stamps its records itself
+        if Sink::Memory.enabled().load(Ordering::Relaxed) {
+            kmsg::record(format_args!("[{:>5}] {}: {}\n", level, target, args));
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/*
+ * Install the logger. Until then everything that is logged is dropped, so
+ * the earlier the better. Calling it twice is a bug.
+ */
+pub fn init() {
+    log::set_logger(&LOGGER).expect("the logger is already installed");
+    set_level(DEFAULT_LEVEL);
+}
+
+/*
+ * The runtime filter. Asking for more than was compiled in gets you what
+ * was compiled in.
+ */
+pub fn set_level(level: LevelFilter) {
+    log::set_max_level(level.min(log::STATIC_MAX_LEVEL));
+}
+
+pub fn level() -> LevelFilter {
+    log::max_level()
+}
+
+pub fn set_sink(sink: Sink, enabled: bool) {
+    sink.enabled().store(enabled, Ordering::Relaxed);
+}
@@ -1,19 +1,35 @@
 #![no_std]
 #![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 
 use core::panic::PanicInfo;
+use rust_os::{console, println, serial_println};
 
-mod vga_buffer;
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::panic_screen::show(info)
+}
 
+#[cfg(test)]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    println!("{}", _info);
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
 }
 
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    // Also puts the console on the screen, with our cursor instead of the BIOS one
+    rust_os::init();
     println!("Hello World!");
+    serial_println!("Hello World!");
     println!("This is some more text");
-    panic!("Panicked in the _start itself :)");
+    log::info!("interrupts are on, the console is on the serial port");
+
+    #[cfg(test)]
+    test_main();
+
+    console::run()
 }
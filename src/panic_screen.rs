@@ -0,0 +1,149 @@
+/*
+ * What is left on the screen after a panic: a red screen that says where the
+ * kernel panicked and why, and the state the CPU was in. Once it is drawn we
+ * stop the CPU for good.
+ *
+ * The registers are read at the start of the panic handler, not where the
+ * panic happened. The general purpose ones only tell part of the story, but
+ * the stack pointer, the flags and the control registers (e.g. CR2, the
+ * address of the last page fault) are still the ones of the code that
+ * panicked.
+ */
+use crate::{kmsg, print, println, serial_println};
+use core::arch::asm;
+use core::panic::PanicInfo;
+use x86_64::registers::control::{Cr0, Cr2, Cr3, Cr4};
+use x86_64::registers::rflags;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Registers {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    rsp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+}
+
+// Copy one register as it is right now
+macro_rules! read_register {
+    ($name:literal) => {{
+        let value: u64;
+        unsafe {
+            asm!(
+                concat!("mov {}, ", $name),
+                out(reg) value,
+                options(nomem, nostack, preserves_flags)
+            );
+        }
+        value
+    }};
+}
+
+/*
+ * Inlined so that we see the registers of the panic handler, not the ones of
+ * a function call that was made just for this.
+ */
+#[inline(always)]
+fn read_registers() -> Registers {
+    Registers {
+        rax: read_register!("rax"),
+        rbx: read_register!("rbx"),
+        rcx: read_register!("rcx"),
+        rdx: read_register!("rdx"),
+        rsi: read_register!("rsi"),
+        rdi: read_register!("rdi"),
+        rbp: read_register!("rbp"),
+        rsp: read_register!("rsp"),
+        r8: read_register!("r8"),
+        r9: read_register!("r9"),
+        r10: read_register!("r10"),
+        r11: read_register!("r11"),
+        r12: read_register!("r12"),
+        r13: read_register!("r13"),
+        r14: read_register!("r14"),
+        r15: read_register!("r15"),
+    }
+}
+
+// Three registers per row, so it fits on an 80 column screen
+fn print_registers(registers: &[(&str, u64)]) {
+    for row in registers.chunks(3) {
+        for (name, value) in row {
+            print!(" {:>6} {:016x}", name, value);
+        }
+        println!();
+    }
+}
+
+#[inline(always)]
+pub fn show(info: &PanicInfo) -> ! {
+    let registers = read_registers();
+    unsafe {
+        crate::force_unlock();
+    }
+    // For when nobody is looking at the screen
+    serial_println!("KERNEL PANIC: {}", info);
+    // What led up to it, as far as the kernel message ring remembers
+    serial_println!("--- kernel messages ---");
+    for record in kmsg::records() {
+        serial_println!("{:>6} {}", record.sequence(), record);
+    }
+    serial_println!("--- end of kernel messages ---");
+
+    // White on red, and a clean screen in those colors
+    print!("\x1b[97;41m\x1b[2J\x1b[H");
+    println!(" KERNEL PANIC");
+    println!();
+    match info.location() {
+        Some(location) => println!(
+            " at {}:{}:{}",
+            location.file(),
+            location.line(),
+            location.column()
+        ),
+        None => println!(" at an unknown location"),
+    }
+    println!(" {}", info.message());
+    println!();
+
+    let r = registers;
+    print_registers(&[
+        ("RAX", r.rax),
+        ("RBX", r.rbx),
+        ("RCX", r.rcx),
+        ("RDX", r.rdx),
+        ("RSI", r.rsi),
+        ("RDI", r.rdi),
+        ("R8", r.r8),
+        ("R9", r.r9),
+        ("R10", r.r10),
+        ("R11", r.r11),
+        ("R12", r.r12),
+        ("R13", r.r13),
+        ("R14", r.r14),
+        ("R15", r.r15),
+        ("RBP", r.rbp),
+        ("RSP", r.rsp),
+        ("RFLAGS", rflags::read_raw()),
+    ]);
+    println!();
+    print_registers(&[
+        ("CR0", Cr0::read_raw()),
+        ("CR2", Cr2::read_raw()),
+        ("CR3", Cr3::read().0.start_address().as_u64()),
+        ("CR4", Cr4::read_raw()),
+    ]);
+
+    crate::halt()
+}
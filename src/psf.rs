@@ -0,0 +1,93 @@
+/*
+ * PC Screen Font (PSF), the bitmap font format of the Linux console.
+ * A PSF file is a small header followed by the glyphs, one after the other.
+ * Every glyph is a bitmap of `height` rows, each row is padded to full bytes
+ * and the leftmost pixel is the highest bit.
+ *
+ * There are two versions of the format:
+ * - PSF1 has a 4 byte header, glyphs are always 8 pixels wide and there are
+ *   either 256 or 512 of them.
+ * - PSF2 has a 32 byte header that gives the width, the height and the number
+ *   of glyphs.
+ * Both can have a unicode table at the end, which we don't need: our fonts
+ * keep their glyphs in code page 437 order, just like the VGA does.
+ */
+
+// PSF1 files start with 0x36 0x04, PSF2 files with 0x72 0xb5 0x4a 0x86
+const PSF1_MODE_512: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Font<'a> {
+    glyphs: &'a [u8],
+    glyph_count: usize,
+    bytes_per_glyph: usize,
+    width: usize,
+    height: usize,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<usize> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+}
+
+impl<'a> Font<'a> {
+    // None if the data is not a PSF font or if it is cut short
+    pub fn parse(data: &'a [u8]) -> Option<Font<'a>> {
+        let (offset, glyph_count, bytes_per_glyph, width, height) = match data {
+            [0x36, 0x04, ..] => {
+                let mode = *data.get(2)?;
+                let height = *data.get(3)? as usize;
+                let glyph_count = if mode & PSF1_MODE_512 != 0 { 512 } else { 256 };
+                (4, glyph_count, height, 8, height)
+            }
+            [0x72, 0xb5, 0x4a, 0x86, ..] => {
+                let header_size = read_u32(data, 8)?;
+                let glyph_count = read_u32(data, 16)?;
+                let bytes_per_glyph = read_u32(data, 20)?;
+                let height = read_u32(data, 24)?;
+                let width = read_u32(data, 28)?;
+                (header_size, glyph_count, bytes_per_glyph, width, height)
+            }
+            _ => return None,
+        };
+        if width == 0 || height == 0 || bytes_per_glyph < width.div_ceil(8) * height {
+            return None;
+        }
+        let glyphs = data.get(offset..offset + glyph_count * bytes_per_glyph)?;
+        Some(Font {
+            glyphs,
+            glyph_count,
+            bytes_per_glyph,
+            width,
+            height,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Nothing needs it yet, the VGA only takes the first 256 glyphs anyway
+    #[allow(dead_code)]
+    pub fn glyph_count(&self) -> usize {
+        self.glyph_count
+    }
+
+    // All the glyphs, one after the other
+    pub fn bitmaps(&self) -> &'a [u8] {
+        self.glyphs
+    }
+
+    // The bitmap of one glyph, height rows of (width + 7) / 8 bytes each
+    pub fn glyph(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.glyph_count {
+            return None;
+        }
+        let start = index * self.bytes_per_glyph;
+        Some(&self.glyphs[start..start + self.bytes_per_glyph])
+    }
+}
@@ -0,0 +1,31 @@
+/*
+ * Talking to QEMU itself. With "-device isa-debug-exit,iobase=0xf4,iosize=0x04"
+ * QEMU has a device at port 0xf4 that makes it exit as soon as something is
+ * written to it, with (value << 1) | 1 as its exit status. So we can't exit
+ * with 0, but any other odd status will do. bootimage turns the one of
+ * Success back into 0, see package.metadata.bootimage in Cargo.toml.
+ */
+use x86_64::instructions::port::Port;
+
+pub const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    // QEMU exits with 33
+    Success = 0x10,
+    // QEMU exits with 35
+    Failed = 0x11,
+}
+
+/*
+ * Without the device, e.g. on real hardware, the write goes nowhere and we
+ * stop right here instead.
+ */
+pub fn exit_qemu(exit_code: QemuExitCode) -> ! {
+    unsafe {
+        let mut port: Port<u32> = Port::new(ISA_DEBUG_EXIT_PORT);
+        port.write(exit_code as u32);
+    }
+    crate::halt()
+}
@@ -0,0 +1,252 @@
+/*
+ * The serial port, a 16550 UART. QEMU connects it to wherever -serial says,
+ * e.g. "-serial stdio" puts everything we send into the terminal QEMU runs
+ * in. Unlike the screen that still works with -display none.
+ *
+ * The UART is programmed through 8 I/O ports starting at its base port. Some
+ * of them mean different things for reading and writing, and while the
+ * "divisor latch access bit" (DLAB) of the line control register is set, the
+ * first two are the divisor that sets the baud rate instead.
+ */
+use core::fmt;
+use lazy_static::lazy_static;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::Port;
+
+mod ring;
+
+use ring::ByteRing;
+
+// The first serial port, where the BIOS and QEMU put it
+pub const COM1: u16 = 0x3F8;
+
+// The baud rate is this clock divided by the divisor
+const UART_CLOCK: u32 = 115_200;
+
+// Offsets of the registers from the base port
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+// With DLAB set
+const DIVISOR_LOW: u16 = 0;
+const DIVISOR_HIGH: u16 = 1;
+
+const LINE_CONTROL_DLAB: u8 = 0x80;
+// 8 data bits, no parity, 1 stop bit
+const LINE_CONTROL_8N1: u8 = 0x03;
+// Enable and clear both FIFOs, interrupt once 14 bytes were received
+const FIFO_ENABLE_CLEAR_14: u8 = 0xC7;
+// Data terminal ready, request to send and OUT2, which gates the interrupt
+const MODEM_DTR_RTS_OUT2: u8 = 0x0B;
+// A received byte is waiting in the data register
+const LINE_STATUS_DATA_READY: u8 = 1 << 0;
+// The transmit holding register is empty, we can send the next byte
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+// Interrupt as soon as there is something to read
+const INTERRUPT_DATA_AVAILABLE: u8 = 1 << 0;
+
+pub struct SerialPort {
+    base: u16,
+}
+
+impl SerialPort {
+    /*
+     * Unsafe because there has to be a UART at base and nobody else may use
+     * it.
+     */
+    pub const unsafe fn new(base: u16) -> SerialPort {
+        SerialPort { base }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        let mut port: Port<u8> = Port::new(self.base + register);
+        unsafe { port.read() }
+    }
+
+    fn write(&mut self, register: u16, value: u8) {
+        let mut port: Port<u8> = Port::new(self.base + register);
+        unsafe { port.write(value) }
+    }
+
+    pub fn init(&mut self, baud_rate: u32) {
+        let divisor = UART_CLOCK.checked_div(baud_rate).unwrap_or(0);
+        assert!(
+            divisor > 0 && divisor * baud_rate == UART_CLOCK,
+            "unsupported baud rate {}",
+            baud_rate
+        );
+        let divisor = divisor as u16;
+
+        // No interrupts until someone asks for them
+        self.write(INTERRUPT_ENABLE, 0x00);
+        self.write(LINE_CONTROL, LINE_CONTROL_DLAB);
+        self.write(DIVISOR_LOW, divisor as u8);
+        self.write(DIVISOR_HIGH, (divisor >> 8) as u8);
+        self.write(LINE_CONTROL, LINE_CONTROL_8N1);
+        self.write(FIFO_CONTROL, FIFO_ENABLE_CLEAR_14);
+        self.write(MODEM_CONTROL, MODEM_DTR_RTS_OUT2);
+    }
+
+    // Wait until the UART can take another byte and hand it over
+    pub fn send(&mut self, byte: u8) {
+        while self.read(LINE_STATUS) & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write(DATA, byte);
+    }
+
+    pub fn receive(&mut self) -> Option<u8> {
+        if self.read(LINE_STATUS) & LINE_STATUS_DATA_READY == 0 {
+            return None;
+        }
+        Some(self.read(DATA))
+    }
+
+    /*
+     * The interrupt goes to the PIC, which also has to let it through, see
+     * interrupts.rs. OUT2 was already set by init().
+     */
+    pub fn enable_receive_interrupt(&mut self) {
+        self.write(INTERRUPT_ENABLE, INTERRUPT_DATA_AVAILABLE);
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    pub static ref SERIAL1: Mutex<SerialPort> = {
+        let mut port = unsafe { SerialPort::new(COM1) };
+        port.init(115_200);
+        Mutex::new(port)
+    };
+}
+
+/*
+ * Program the UART now instead of on the first serial_print!, which could be
+ * from a fault handler on its small stack of the TSS, see gdt.rs.
+ */
+pub fn init() {
+    lazy_static::initialize(&SERIAL1);
+}
+
+// What came in over COM1 and wasn't read yet
+static INPUT: ByteRing = ByteRing::new();
+
+/*
+ * Called by the interrupt handler of COM1. With the FIFO there can be more
+ * than one byte waiting, we take them all. Once the ring is full, whatever
+ * comes in is lost.
+ * This doesn't go through SERIAL1: the interrupt may have come while it is
+ * locked for sending. Receiving only touches the line status and the data
+ * register, which sending doesn't mind.
+ */
+pub fn handle_interrupt() {
+    let mut port = unsafe { SerialPort::new(COM1) };
+    while let Some(byte) = port.receive() {
+        INPUT.push(byte);
+    }
+}
+
+// Start receiving with interrupts, the interrupt handler has to be in place
+pub fn enable_input() {
+    interrupts::without_interrupts(|| SERIAL1.lock().enable_receive_interrupt());
+}
+
+// The next byte we received, if there is one
+pub fn read_byte() -> Option<u8> {
+    INPUT.pop()
+}
+
+/*
+ * Everything that comes in over the serial port, as it comes in. next()
+ * waits for the next byte, so this never ends. Interrupts are on or off
+ * afterwards just like they were before.
+ */
+pub struct Input;
+
+impl Iterator for Input {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let were_enabled = interrupts::are_enabled();
+        loop {
+            /*
+             * Interrupts are off between looking at the ring and hlt, a byte
+             * that comes in right in between wakes us up right away.
+             */
+            interrupts::disable();
+            if let Some(byte) = read_byte() {
+                if were_enabled {
+                    interrupts::enable();
+                }
+                return Some(byte);
+            }
+            if were_enabled {
+                interrupts::enable_and_hlt();
+            } else {
+                /*
+                 * The caller wants interrupts off, so the interrupt handler
+                 * never runs and hlt would never return. Fetch the bytes
+                 * from the UART ourselves instead.
+                 */
+                handle_interrupt();
+                core::hint::spin_loop();
+            }
+        }
+    }
+}
+
+/*
+ * serial_print! for where waiting for SERIAL1 might never end, e.g. in the
+ * handler of an NMI. It doesn't lock anything and sends through a port of
+ * its own, so if someone else is sending right now our text ends up in the
+ * middle of theirs.
+ */
+pub fn print_unlocked(args: fmt::Arguments) {
+    use core::fmt::Write;
+    let mut port = unsafe { SerialPort::new(COM1) };
+    let _ = port.write_fmt(args);
+}
+
+/*
+ * Part of crate::force_unlock(). Breaks SERIAL1, which the panic may have hit
+ * in the middle of a serial_print!. The UART has no state of its own beyond
+ * the byte being sent, so at worst that line gets cut off.
+ */
+pub unsafe fn force_unlock() {
+    SERIAL1.force_unlock();
+}
+
+/*
+ * Just like print! and println!, but to the serial port. The lock is taken
+ * with interrupts off for the same reason as there.
+ */
+#[macro_export]
+macro_rules! serial_print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! serial_println {
+    () => ($crate::serial_print!("\n"));
+    ($($arg:tt)*) => ($crate::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+    interrupts::without_interrupts(|| {
+        SERIAL1.lock().write_fmt(args).unwrap();
+    });
+}
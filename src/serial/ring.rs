@@ -0,0 +1,92 @@
+/*
+ * A ring buffer for bytes that doesn't need a lock. There is exactly one
+ * producer, the interrupt handler of the UART, and exactly one consumer, the
+ * code that reads the input. A lock wouldn't work here: if the interrupt came
+ * while the reader holds it, the handler would wait forever.
+ *
+ * head and tail only ever count up (and wrap around), the slot of a byte is
+ * its count modulo the size. The producer only writes tail, the consumer only
+ * writes head. A byte is written before tail moves past it (Release) and the
+ * consumer only reads it after it saw that tail (Acquire), and the other way
+ * around for freeing up slots.
+ */
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+// A power of two, so that the counters can wrap around without a jump
+pub const RING_SIZE: usize = 256;
+
+pub struct ByteRing {
+    bytes: [AtomicU8; RING_SIZE],
+    // Count of the next byte to read
+    head: AtomicUsize,
+    // Count of the next byte to write
+    tail: AtomicUsize,
+}
+
+impl ByteRing {
+    pub const fn new() -> ByteRing {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: AtomicU8 = AtomicU8::new(0);
+        ByteRing {
+            bytes: [EMPTY; RING_SIZE],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    // Producer only. When the ring is full the byte is dropped, false then
+    pub fn push(&self, byte: u8) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == RING_SIZE {
+            return false;
+        }
+        self.bytes[tail % RING_SIZE].store(byte, Ordering::Relaxed);
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    // Consumer only
+    pub fn pop(&self) -> Option<u8> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+        let byte = self.bytes[head % RING_SIZE].load(Ordering::Relaxed);
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_come_out_in_order() {
+        let ring = ByteRing::new();
+        assert_eq!(ring.pop(), None);
+        for byte in b"abc" {
+            assert!(ring.push(*byte));
+        }
+        assert_eq!(ring.pop(), Some(b'a'));
+        assert_eq!(ring.pop(), Some(b'b'));
+        assert_eq!(ring.pop(), Some(b'c'));
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test]
+    fn full_ring_drops_new_bytes() {
+        let ring = ByteRing::new();
+        for count in 0..RING_SIZE {
+            assert!(ring.push(count as u8));
+        }
+        assert!(!ring.push(0xff));
+        assert_eq!(ring.pop(), Some(0));
+        assert!(ring.push(0xff));
+        let rest: Vec<u8> = core::iter::from_fn(|| ring.pop()).collect();
+        assert_eq!(rest.len(), RING_SIZE);
+        assert_eq!(rest.last(), Some(&0xff));
+    }
+}
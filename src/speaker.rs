@@ -0,0 +1,55 @@
+/*
+ * The PC speaker, what the consoles do for the bell character.
+ *
+ * Channel 2 of the programmable interval timer (the same chip that gives us
+ * the timer interrupt on channel 0) makes a square wave, and two bits of
+ * port 0x61 connect it to the speaker. A beep is started right away, the
+ * timer interrupt turns it off once its time is up. Before interrupts are on
+ * there is no timer interrupt, so an early beep goes on until there is.
+ */
+use crate::interrupts::{uptime_ms, PIT_FREQUENCY};
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::instructions::port::Port;
+
+const PIT_CHANNEL_2_PORT: u16 = 0x42;
+const PIT_COMMAND_PORT: u16 = 0x43;
+// Channel 2, low byte then high byte of the divisor, square wave
+const PIT_CHANNEL_2_SQUARE_WAVE: u8 = 0xb6;
+
+const SPEAKER_PORT: u16 = 0x61;
+// Let channel 2 run and connect it to the speaker
+const SPEAKER_ENABLE: u8 = 0x03;
+
+const BEEP_FREQUENCY: u64 = 880;
+const BEEP_MS: u64 = 100;
+
+// When the beep is over, in uptime_ms(). 0 while the speaker is quiet
+static STOP_AT_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn beep() {
+    let divisor = (PIT_FREQUENCY / BEEP_FREQUENCY) as u16;
+    unsafe {
+        Port::new(PIT_COMMAND_PORT).write(PIT_CHANNEL_2_SQUARE_WAVE);
+        let mut channel: Port<u8> = Port::new(PIT_CHANNEL_2_PORT);
+        channel.write(divisor as u8);
+        channel.write((divisor >> 8) as u8);
+        let mut speaker: Port<u8> = Port::new(SPEAKER_PORT);
+        let value = speaker.read();
+        speaker.write(value | SPEAKER_ENABLE);
+    }
+    STOP_AT_MS.store(uptime_ms() + BEEP_MS, Ordering::Relaxed);
+}
+
+// Called by the timer interrupt
+pub fn tick() {
+    let stop_at = STOP_AT_MS.load(Ordering::Relaxed);
+    if stop_at == 0 || uptime_ms() < stop_at {
+        return;
+    }
+    STOP_AT_MS.store(0, Ordering::Relaxed);
+    unsafe {
+        let mut speaker: Port<u8> = Port::new(SPEAKER_PORT);
+        let value = speaker.read();
+        speaker.write(value & !SPEAKER_ENABLE);
+    }
+}
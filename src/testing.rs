@@ -0,0 +1,256 @@
+/*
+ * The kernel test framework. There is no standard library and so no test
+ * harness, but the custom_test_frameworks feature collects all #[test_case]
+ * functions of a test binary and hands them to test_runner() below, which
+ * runs them one after the other.
+ *
+ * The results go out over the serial port, which bootimage connects to its
+ * stdout, and QEMU is told to exit through qemu::exit_qemu(). The first
+ * failing test panics, the panic handler of the test binary calls
+ * test_panic_handler() and that is the end of that binary. So is a test that
+ * doesn't finish within the timeout, see check_timeout(). The test runner
+ * hooks that into the timer interrupt, the kernel itself never calls it.
+ *
+ * A test binary looks like this, see tests/basic_boot.rs:
+ *
+ *  #![no_std]
+ *  #![no_main]
+ *  #![feature(custom_test_frameworks)]
+ *  #![test_runner(rust_os::testing::test_runner)]
+ *  #![reexport_test_harness_main = "test_main"]
+ *
+ *  #[no_mangle]
+ *  pub extern "C" fn _start() -> ! {
+ *      rust_os::init();
+ *      test_main();
+ *      unreachable!("the test runner exits QEMU");
+ *  }
+ *
+ *  #[panic_handler]
+ *  fn panic(info: &PanicInfo) -> ! {
+ *      rust_os::testing::test_panic_handler(info)
+ *  }
+ */
+use crate::interrupts::{set_timer_hook, uptime_ms};
+use crate::qemu::{self, QemuExitCode};
+use crate::{serial_print, serial_println};
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+pub mod snapshot;
+
+// How long a test may take until set_test_timeout() says otherwise
+const DEFAULT_TEST_TIMEOUT_MS: u64 = 10_000;
+
+static TEST_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_TEST_TIMEOUT_MS);
+// When the running test times out, in uptime_ms(). 0 while no test runs
+static DEADLINE_MS: AtomicU64 = AtomicU64::new(0);
+/*
+ * The name of the running test, for the timeout message. It is read by the
+ * timer interrupt, which must not wait for a lock, so it is kept as the
+ * pointer and the length of the &'static str. Both are only changed while
+ * DEADLINE_MS is 0, when the timer doesn't look at them.
+ */
+static RUNNING_TEST: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static RUNNING_TEST_LENGTH: AtomicUsize = AtomicUsize::new(0);
+// What the message of should_panic_with() has to start with, kept the same way
+static EXPECTED_PANIC: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static EXPECTED_PANIC_LENGTH: AtomicUsize = AtomicUsize::new(0);
+// Set by should_time_out(), then a timeout is what we want
+static EXPECT_TIMEOUT: AtomicBool = AtomicBool::new(false);
+
+// Anything that can be run as a test, which is any function without arguments
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        let name = core::any::type_name::<T>();
+        serial_print!("{}...\t", name);
+        start_timeout(name);
+        self();
+        stop_timeout();
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) -> ! {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    qemu::exit_qemu(QemuExitCode::Success)
+}
+
+pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    unsafe {
+        crate::force_unlock();
+    }
+    stop_timeout();
+    serial_println!("[failed]\n");
+    serial_println!("Error: {}\n", info);
+    qemu::exit_qemu(QemuExitCode::Failed)
+}
+
+/*
+ * For a test that passes by panicking. It needs a test binary of its own
+ * with harness = false in Cargo.toml, see tests/should_panic.rs: its _start
+ * calls should_panic() with the test and its panic handler calls
+ * should_panic_handler(). The first panic ends the binary either way, so
+ * there is one such test per binary.
+ */
+pub fn should_panic<T: Fn()>(test: T) -> ! {
+    should_panic_with(test, "")
+}
+
+/*
+ * Like should_panic(), but any other panic fails the test: the message has
+ * to start with expected, e.g. "EXCEPTION: PAGE FAULT" for a test that
+ * wants the page fault handler and not just some panic on the way there.
+ */
+pub fn should_panic_with<T: Fn()>(test: T, expected: &'static str) -> ! {
+    EXPECTED_PANIC.store(expected.as_ptr() as *mut u8, Ordering::Relaxed);
+    EXPECTED_PANIC_LENGTH.store(expected.len(), Ordering::Relaxed);
+    let name = core::any::type_name::<T>();
+    serial_print!("{}...\t", name);
+    start_timeout(name);
+    test();
+    stop_timeout();
+    serial_println!("[test did not panic]");
+    qemu::exit_qemu(QemuExitCode::Failed)
+}
+
+pub fn should_panic_handler(info: &PanicInfo) -> ! {
+    unsafe {
+        crate::force_unlock();
+    }
+    stop_timeout();
+    let expected = load_str(&EXPECTED_PANIC, &EXPECTED_PANIC_LENGTH);
+    if !starts_with(info.message(), expected) {
+        serial_println!("[failed]\n");
+        serial_println!("Error: {}\n", info);
+        serial_println!("expected a panic starting with '{}'\n", expected);
+        qemu::exit_qemu(QemuExitCode::Failed)
+    }
+    serial_println!("[ok]");
+    qemu::exit_qemu(QemuExitCode::Success)
+}
+
+// Without formatting the whole message into a buffer, there is no heap
+fn starts_with(message: impl fmt::Display, prefix: &str) -> bool {
+    struct Prefix<'a> {
+        rest: &'a [u8],
+        matches: bool,
+    }
+
+    impl fmt::Write for Prefix<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let length = s.len().min(self.rest.len());
+            self.matches &= s.as_bytes()[..length] == self.rest[..length];
+            self.rest = &self.rest[length..];
+            Ok(())
+        }
+    }
+
+    let mut checker = Prefix {
+        rest: prefix.as_bytes(),
+        matches: true,
+    };
+    let _ = write!(checker, "{}", message);
+    checker.matches && checker.rest.is_empty()
+}
+
+/*
+ * For a test that passes by hanging, to test the timeout itself. Like
+ * should_panic() it needs a test binary of its own with harness = false, see
+ * tests/timeout.rs, and it can only work with interrupts on.
+ */
+pub fn should_time_out<T: Fn()>(test: T) -> ! {
+    EXPECT_TIMEOUT.store(true, Ordering::Relaxed);
+    let name = core::any::type_name::<T>();
+    serial_print!("{}...\t", name);
+    start_timeout(name);
+    test();
+    stop_timeout();
+    serial_println!("[test did not time out]");
+    qemu::exit_qemu(QemuExitCode::Failed)
+}
+
+// For the tests that come after, e.g. in a test binary with slow tests
+pub fn set_test_timeout(milliseconds: u64) {
+    TEST_TIMEOUT_MS.store(milliseconds, Ordering::Relaxed);
+}
+
+fn start_timeout(name: &'static str) {
+    set_timer_hook(check_timeout);
+    RUNNING_TEST.store(name.as_ptr() as *mut u8, Ordering::Relaxed);
+    RUNNING_TEST_LENGTH.store(name.len(), Ordering::Relaxed);
+    let deadline = uptime_ms() + TEST_TIMEOUT_MS.load(Ordering::Relaxed);
+    // Release: the timer sees the name before it sees the deadline
+    DEADLINE_MS.store(deadline, Ordering::Release);
+}
+
+fn running_test() -> &'static str {
+    load_str(&RUNNING_TEST, &RUNNING_TEST_LENGTH)
+}
+
+// A &'static str that was stored as its pointer and its length
+fn load_str(pointer: &AtomicPtr<u8>, length: &AtomicUsize) -> &'static str {
+    let pointer = pointer.load(Ordering::Relaxed);
+    let length = length.load(Ordering::Relaxed);
+    if pointer.is_null() {
+        return "";
+    }
+    // Safe because the two always come from the same &'static str
+    unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(pointer, length)) }
+}
+
+fn stop_timeout() {
+    DEADLINE_MS.store(0, Ordering::Relaxed);
+}
+
+/*
+ * Called by the timer interrupt. A test that hangs never gets back to the
+ * test runner, so it is the timer that fails it. That only works as long as
+ * interrupts are on: a test that hangs with interrupts off is left to the
+ * test-timeout of bootimage, which kills QEMU without telling which test it
+ * was.
+ */
+fn check_timeout() {
+    let deadline = DEADLINE_MS.load(Ordering::Acquire);
+    if deadline == 0 || uptime_ms() < deadline {
+        return;
+    }
+    unsafe {
+        crate::force_unlock();
+    }
+    if EXPECT_TIMEOUT.load(Ordering::Relaxed) {
+        serial_println!("[ok]");
+        qemu::exit_qemu(QemuExitCode::Success)
+    }
+    serial_println!("[timeout]\n");
+    serial_println!(
+        "Error: {} didn't finish within {}ms\n",
+        running_test(),
+        TEST_TIMEOUT_MS.load(Ordering::Relaxed)
+    );
+    qemu::exit_qemu(QemuExitCode::Failed)
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_over_several_pieces() {
+        let message = format_args!("EXCEPTION: {} at {:#x}", "PAGE FAULT", 0x1234);
+        assert!(starts_with(message, "EXCEPTION: PAGE FAULT"));
+        assert!(starts_with(message, "EXCEPTION: PAGE FAULT at 0x1234"));
+        assert!(starts_with(message, ""));
+        assert!(!starts_with(message, "EXCEPTION: DOUBLE FAULT"));
+        // Longer than the whole message
+        assert!(!starts_with(message, "EXCEPTION: PAGE FAULT at 0x12345"));
+    }
+}
@@ -0,0 +1,215 @@
+/*
+ * Screen snapshots for the kernel tests. A snapshot is what the screen shows,
+ * read back from the VGA buffer: the text and the colors of every character.
+ * A test compares it to what it expects the screen to show, its golden
+ * snapshot. If they differ the test fails, and both go out over the serial
+ * port row by row, so the diff ends up in the test output.
+ *
+ * Output goes to the bottom of the screen, so that is what the golden rows
+ * are lined up with: the last golden row is the last row of the screen, and
+ * so on upwards. E.g. after println!("a"); println!("b"):
+ *
+ *     snapshot::assert_text(&["a", "b", ""]);
+ *
+ * Blanks at the end of a row don't count. A '*' at the end of a golden text
+ * row stands for whatever the rest of the row is, for things like addresses
+ * that change from build to build:
+ *
+ *     snapshot::assert_text(&["instruction_pointer: 0x*"]);
+ *
+ * Colors are one hex digit per
+ * character, its foreground or background color number (see Color), and only
+ * as many characters as the golden row is long are compared. After
+ * print_colored!(Color::LightRed, Color::Blue, "red"):
+ *
+ *     snapshot::assert_foreground(&["ccc"]);
+ *     snapshot::assert_background(&["111"]);
+ */
+use crate::serial_println;
+use crate::vga_buffer::{
+    self, cp437, ScreenChar, TextSurface, Writer, MAX_BUFFER_HEIGHT, MAX_BUFFER_WIDTH,
+};
+use core::fmt;
+
+// What part of the characters a golden snapshot is about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aspect {
+    Text,
+    Foreground,
+    Background,
+}
+
+pub struct Snapshot {
+    width: usize,
+    height: usize,
+    chars: [[ScreenChar; MAX_BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+}
+
+impl Snapshot {
+    // The console that is on the screen, as it is right now
+    pub fn capture() -> Snapshot {
+        vga_buffer::with_console(vga_buffer::active_console(), |writer| Snapshot::of(writer))
+    }
+
+    pub fn of<S: TextSurface>(writer: &Writer<S>) -> Snapshot {
+        let (width, height) = (writer.width(), writer.height());
+        let mut chars = [[writer.read_char(0, 0); MAX_BUFFER_WIDTH]; MAX_BUFFER_HEIGHT];
+        for (row, line) in chars[..height].iter_mut().enumerate() {
+            for (col, character) in line[..width].iter_mut().enumerate() {
+                *character = writer.read_char(row, col);
+            }
+        }
+        Snapshot {
+            width,
+            height,
+            chars,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col]
+    }
+
+    // The screen row the first golden row is lined up with
+    fn first_row(&self, golden: &[&str]) -> usize {
+        self.height.checked_sub(golden.len()).unwrap_or_else(|| {
+            panic!(
+                "{} golden rows, but the screen only has {}",
+                golden.len(),
+                self.height
+            )
+        })
+    }
+
+    pub fn matches(&self, aspect: Aspect, golden: &[&str]) -> bool {
+        let first_row = self.first_row(golden);
+        golden
+            .iter()
+            .enumerate()
+            .all(|(offset, expected)| self.row_matches(aspect, first_row + offset, expected))
+    }
+
+    fn row_matches(&self, aspect: Aspect, row: usize, expected: &str) -> bool {
+        let line = &self.chars[row][..self.width];
+        match aspect {
+            Aspect::Text => {
+                let (expected, anything_after) = match expected.strip_suffix('*') {
+                    Some(start) => (start, true),
+                    None => (expected, false),
+                };
+                let length = expected.chars().count();
+                length <= line.len()
+                    && expected.chars().zip(line).all(|(c, character)| {
+                        character.character() == cp437::from_char(c).unwrap_or(0xfe)
+                    })
+                    // Unless there is a '*', whatever the golden row doesn't cover has to be blank
+                    && (anything_after || line[length..].iter().all(|c| c.character() == b' '))
+            }
+            Aspect::Foreground | Aspect::Background => {
+                expected.chars().count() <= line.len()
+                    && expected.chars().zip(line).all(|(digit, &character)| {
+                        digit.to_digit(16) == Some(u32::from(color(aspect, character)))
+                    })
+            }
+        }
+    }
+
+    /*
+     * Fail the test unless the screen matches, with the golden and the
+     * actual rows on the serial port. Rows that match are shown once, the
+     * others as "-" golden and "+" actual.
+     */
+    #[track_caller]
+    pub fn assert(&self, aspect: Aspect, golden: &[&str]) {
+        if self.matches(aspect, golden) {
+            return;
+        }
+        let first_row = self.first_row(golden);
+        // The test name is still waiting for its result on the serial port
+        serial_println!();
+        serial_println!("The {:?} of the screen doesn't match the snapshot:", aspect);
+        for (offset, expected) in golden.iter().enumerate() {
+            let row = first_row + offset;
+            let actual = ShowRow {
+                snapshot: self,
+                aspect,
+                row,
+                length: expected.chars().count(),
+            };
+            if self.row_matches(aspect, row, expected) {
+                serial_println!("  {:>2} |{}", row, actual);
+            } else {
+                serial_println!("- {:>2} |{}", row, expected);
+                serial_println!("+ {:>2} |{}", row, actual);
+            }
+        }
+        panic!("the screen doesn't match the snapshot, see above");
+    }
+}
+
+fn color(aspect: Aspect, character: ScreenChar) -> u8 {
+    match aspect {
+        Aspect::Background => character.color_code().background(),
+        _ => character.color_code().foreground(),
+    }
+}
+
+// A row of a snapshot the way the golden rows are written
+struct ShowRow<'a> {
+    snapshot: &'a Snapshot,
+    aspect: Aspect,
+    row: usize,
+    // How many colors the golden row has
+    length: usize,
+}
+
+impl fmt::Display for ShowRow<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = &self.snapshot.chars[self.row][..self.snapshot.width];
+        match self.aspect {
+            Aspect::Text => {
+                let text = match line.iter().rposition(|c| c.character() != b' ') {
+                    Some(last) => &line[..=last],
+                    None => &[],
+                };
+                for character in text {
+                    match character.character() {
+                        byte @ b' '..=b'~' => write!(f, "{}", byte as char)?,
+                        // Everything else the way a golden row can't mistake it
+                        byte => write!(f, "\\x{:02x}", byte)?,
+                    }
+                }
+                Ok(())
+            }
+            aspect => {
+                for &character in line.iter().take(self.length) {
+                    write!(f, "{:x}", color(aspect, character))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[track_caller]
+pub fn assert_text(golden: &[&str]) {
+    Snapshot::capture().assert(Aspect::Text, golden);
+}
+
+#[track_caller]
+pub fn assert_foreground(golden: &[&str]) {
+    Snapshot::capture().assert(Aspect::Foreground, golden);
+}
+
+#[track_caller]
+pub fn assert_background(golden: &[&str]) {
+    Snapshot::capture().assert(Aspect::Background, golden);
+}
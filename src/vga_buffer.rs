@@ -1,7 +1,25 @@
 use core::fmt;
+use core::ops::Range;
 use volatile::Volatile;
 
-#[allow(dead_code)]
+pub mod ansi;
+pub mod cp437;
+mod cursor;
+pub mod graphics;
+mod mode;
+mod region;
+mod registers;
+mod scrollback;
+mod surface;
+
+use crate::{framebuffer, kmsg, psf, speaker};
+pub use mode::TextMode;
+pub use region::{Region, RegionWriter};
+use scrollback::Scrollback;
+#[cfg(all(test, not(target_os = "none")))]
+pub use surface::MemoryBuffer;
+pub use surface::TextSurface;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Color {
@@ -23,110 +41,699 @@ pub enum Color {
     White = 15,
 }
 
+/*
+ * What the colors look like out of the box, as 8 bit red, green and blue.
+ * Brown is the odd one out, it would be a dark yellow otherwise.
+ */
+pub const DEFAULT_PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00],
+    [0x00, 0x00, 0xaa],
+    [0x00, 0xaa, 0x00],
+    [0x00, 0xaa, 0xaa],
+    [0xaa, 0x00, 0x00],
+    [0xaa, 0x00, 0xaa],
+    [0xaa, 0x55, 0x00],
+    [0xaa, 0xaa, 0xaa],
+    [0x55, 0x55, 0x55],
+    [0x55, 0x55, 0xff],
+    [0x55, 0xff, 0x55],
+    [0x55, 0xff, 0xff],
+    [0xff, 0x55, 0x55],
+    [0xff, 0x55, 0xff],
+    [0xff, 0xff, 0x55],
+    [0xff, 0xff, 0xff],
+];
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub const fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
+
+    /*
+     * The escape sequences work on the raw 4 bit color numbers, e.g. "bold"
+     * just sets the bright bit of whatever foreground color is in use.
+     */
+    pub fn foreground(self) -> u8 {
+        self.0 & 0x0f
+    }
+
+    pub fn background(self) -> u8 {
+        self.0 >> 4
+    }
+
+    fn with_foreground(self, foreground: u8) -> ColorCode {
+        ColorCode((self.0 & 0xf0) | (foreground & 0x0f))
+    }
+
+    fn with_background(self, background: u8) -> ColorCode {
+        ColorCode((background & 0x0f) << 4 | (self.0 & 0x0f))
+    }
+
+    /*
+     * SGR, the "m" escape sequence, sets the colors. It can carry any number
+     * of attributes, an empty one ("\x1b[m") is the same as a reset to
+     * default. Bright backgrounds set the top attribute bit, which the VGA
+     * shows as blinking text by default, see set_blink().
+     */
+    pub fn select_graphic_rendition(self, params: &[u16], default: ColorCode) -> ColorCode {
+        if params.is_empty() {
+            return default;
+        }
+        params.iter().fold(self, |color, &param| match param {
+            0 => default,
+            // Bold, rendered as the bright variant of the color
+            1 => color.with_foreground(color.foreground() | 0x8),
+            22 => color.with_foreground(color.foreground() & 0x7),
+            // Reverse video
+            7 => ColorCode(color.0.rotate_left(4)),
+            30..=37 => color.with_foreground(ansi::color(param - 30, false) as u8),
+            39 => color.with_foreground(default.foreground()),
+            40..=47 => color.with_background(ansi::color(param - 40, false) as u8),
+            49 => color.with_background(default.background()),
+            90..=97 => color.with_foreground(ansi::color(param - 90, true) as u8),
+            100..=107 => color.with_background(ansi::color(param - 100, true) as u8),
+            _ => color,
+        })
+    }
 }
 
 /* Structure that encapsulates what needs to be displayed on the screen */
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(C)]
-struct ScreenChar {
+pub struct ScreenChar {
     ascii_character: u8,
     color_code: ColorCode,
 }
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+impl ScreenChar {
+    // The CP437 code of the character, see cp437.rs
+    pub fn character(self) -> u8 {
+        self.ascii_character
+    }
+
+    pub fn color_code(self) -> ColorCode {
+        self.color_code
+    }
+}
+
+/*
+ * The size of the screen depends on the text mode (see mode.rs), these are
+ * the limits of the biggest one, 90x60.
+ */
+pub const MAX_BUFFER_HEIGHT: usize = 60;
+pub const MAX_BUFFER_WIDTH: usize = 90;
+
+// Tab stops are every 8 columns
+pub const TAB_WIDTH: usize = 8;
 
 #[repr(transparent)]
-struct Buffer {
+pub struct Buffer {
     /*
-     * This means that we can BUFFER WIDTH number of screen characters and we
-     * can have BUFFER_HEIGHT of them.
-     * In this case: We can have 80 characters per line and we have 25 of such
+     * The screen is one long line of screen characters, the VGA starts a new
+     * row after every <width> of them. How wide a row is depends on the text
+     * mode, so there is room for the biggest one and a character lives at
+     * chars[row * width + col].
+     * In 80x25 mode: We can have 80 characters per line and we have 25 of such
      * lines.
      * The volatile keyword suggests that even if we only do a write and there
      * no affect on the RAM, there can be other side effects and therefore must
      * not be optimized out.
      * old: chars: [[ScreenChar; BUFFER_WIDTH]; BUFFER_HEIGHT],
      */
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    chars: [Volatile<ScreenChar>; MAX_BUFFER_WIDTH * MAX_BUFFER_HEIGHT],
+}
+
+/*
+ * Every virtual console draws into its own copy of the screen that lives in
+ * normal memory. Only the console that is being shown owns the VGA buffer,
+ * and its writes go to both places. Switching consoles hands the VGA buffer
+ * over to the new console, which copies its screen into it.
+ */
+struct ConsoleBuffer<S: 'static> {
+    chars: [[ScreenChar; MAX_BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+    width: usize,
+    height: usize,
+    hardware: Option<&'static mut S>,
+}
+
+impl<S: TextSurface> ConsoleBuffer<S> {
+    const fn off_screen(mode: TextMode) -> ConsoleBuffer<S> {
+        ConsoleBuffer {
+            chars: [[BLANK; MAX_BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+            width: mode.width(),
+            height: mode.height(),
+            hardware: None,
+        }
+    }
+
+    fn is_visible(&self) -> bool {
+        self.hardware.is_some()
+    }
+
+    fn read(&self, row: usize, col: usize) -> ScreenChar {
+        self.chars[row][col]
+    }
+
+    fn write(&mut self, row: usize, col: usize, character: ScreenChar) {
+        self.chars[row][col] = character;
+        if let Some(hardware) = &mut self.hardware {
+            hardware.write(row * self.width + col, character);
+        }
+    }
+
+    // Go on the screen, and go on with whatever is on it already
+    fn adopt(&mut self, hardware: &'static mut S) {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                self.chars[row][col] = hardware.read(row * self.width + col);
+            }
+        }
+        self.hardware = Some(hardware);
+    }
+
+    fn attach(&mut self, hardware: &'static mut S) {
+        for row in 0..self.height {
+            for col in 0..self.width {
+                hardware.write(row * self.width + col, self.chars[row][col]);
+            }
+        }
+        self.hardware = Some(hardware);
+    }
+
+    fn detach(&mut self) -> Option<&'static mut S> {
+        self.hardware.take()
+    }
+
+    // Consoles in the background don't get to touch the cursor
+    fn enable_cursor(&mut self, start: u8, end: u8) {
+        if let Some(hardware) = &mut self.hardware {
+            hardware.enable_cursor(start, end);
+        }
+    }
+
+    fn disable_cursor(&mut self) {
+        if let Some(hardware) = &mut self.hardware {
+            hardware.disable_cursor();
+        }
+    }
+
+    fn move_cursor(&mut self, row: usize, col: usize) {
+        if let Some(hardware) = &mut self.hardware {
+            hardware.move_cursor(row * self.width + col);
+        }
+    }
 }
 
-pub struct Writer {
+// Underline cursor, the last two scanlines of the character
+const fn default_cursor_shape(mode: TextMode) -> (u8, u8) {
+    let font_height = mode.font_height() as u8;
+    (font_height - 2, font_height - 1)
+}
+
+/*
+ * The Writer doesn't care what it draws on, see surface.rs. The consoles draw
+ * on the VGA buffer, which is the default.
+ */
+pub struct Writer<S: 'static = Buffer> {
     column_position: usize,
+    /*
+     * Normally we only ever write to the last row, but escape sequences can
+     * move the cursor anywhere on the screen.
+     */
+    row_position: usize,
     color_code: ColorCode,
-    // Lifetime valid for the whole program run
-    buffer: &'static mut Buffer,
+    // The colors "\x1b[0m" goes back to
+    default_color: ColorCode,
+    cursor_shape: (u8, u8),
+    cursor_visible: bool,
+    parser: ansi::Parser,
+    scrollback: Scrollback,
+    /*
+     * How many rows we are scrolled back, 0 means we are looking at the live
+     * screen. While scrolled back, the live screen is kept in live_rows.
+     */
+    scroll_offset: usize,
+    live_rows: [scrollback::Row; MAX_BUFFER_HEIGHT],
+    // Called for "\x07", the consoles beep, see console()
+    bell: Option<fn()>,
+    buffer: ConsoleBuffer<S>,
 }
 
-impl Writer {
+impl<S: TextSurface> Writer<S> {
+    const fn new(buffer: ConsoleBuffer<S>, mode: TextMode) -> Writer<S> {
+        let color_code = ColorCode::new(Color::Yellow, Color::Black);
+        Writer {
+            column_position: 0,
+            row_position: buffer.height - 1,
+            color_code,
+            default_color: color_code,
+            cursor_shape: default_cursor_shape(mode),
+            cursor_visible: true,
+            parser: ansi::Parser::new(),
+            scrollback: Scrollback::new(BLANK),
+            scroll_offset: 0,
+            live_rows: [[BLANK; MAX_BUFFER_WIDTH]; MAX_BUFFER_HEIGHT],
+            bell: None,
+            buffer,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.buffer.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.buffer.height
+    }
+
+    /*
+     * What the screen of this console shows at row, col. Read back from the
+     * screen itself while the console is on it.
+     */
+    pub fn read_char(&self, row: usize, col: usize) -> ScreenChar {
+        match &self.buffer.hardware {
+            Some(hardware) => hardware.read(row * self.width() + col),
+            None => self.buffer.read(row, col),
+        }
+    }
+
+    /*
+     * The text mode changed. The output is at the bottom of the screen, so
+     * that's where the rows stay: if there are fewer rows now the top ones go
+     * into the scrollback, if there are more we get empty ones on top.
+     * Columns that don't fit anymore are cut off.
+     */
+    fn resize(&mut self, mode: TextMode) {
+        self.scroll_to_bottom();
+        let (width, height) = (mode.width(), mode.height());
+        let (old_width, old_height) = (self.width(), self.height());
+        let blank = ScreenChar {
+            ascii_character: b' ',
+            color_code: self.color_code,
+        };
+        let chars = &mut self.buffer.chars;
+        if height < old_height {
+            let lost = old_height - height;
+            for &row in chars[..lost].iter() {
+                self.scrollback.push(row);
+            }
+            chars.copy_within(lost..old_height, 0);
+            self.row_position = self.row_position.saturating_sub(lost);
+        } else {
+            let gained = height - old_height;
+            chars.copy_within(0..old_height, gained);
+            chars[..gained].fill([blank; MAX_BUFFER_WIDTH]);
+            self.row_position += gained;
+        }
+        if width > old_width {
+            for line in chars[..height].iter_mut() {
+                line[old_width..width].fill(blank);
+            }
+        }
+        self.buffer.width = width;
+        self.buffer.height = height;
+        self.column_position = self.column_position.min(width);
+        self.cursor_shape = default_cursor_shape(mode);
+    }
+
+    /*
+     * The control characters do what they do on a terminal, all the other
+     * bytes are written as they are.
+     */
     pub fn write_byte(&mut self, byte: u8) {
+        self.scroll_to_bottom();
         match byte {
             b'\n' => self.new_line(),
-            byte => {
-                if self.column_position >= BUFFER_WIDTH {
-                    self.new_line();
+            b'\r' => {
+                self.column_position = 0;
+                self.update_cursor();
+            }
+            b'\t' => {
+                let next_stop = (self.column_position / TAB_WIDTH + 1) * TAB_WIDTH;
+                // Like at the end of a line, the next character wraps
+                self.column_position = next_stop.min(self.width());
+                self.update_cursor();
+            }
+            // Backspace, the character it goes back over is erased
+            0x08 => {
+                if self.column_position > 0 {
+                    let col = self.column_position.min(self.width()) - 1;
+                    self.clear_columns(self.row_position, col..col + 1);
+                    self.column_position = col;
+                    self.update_cursor();
                 }
-                let row = BUFFER_HEIGHT - 1;
-                let col = self.column_position;
-                let color_code = self.color_code;
-                let character = ScreenChar {
-                    ascii_character: byte,
-                    color_code: color_code,
-                };
-
-                /*
-                 * Equivalent to self.buffer.chars[row][col] = character;
-                 * We are using write because we have defined it as volatile and
-                 * we don't want this stuff to get compiled out
-                 */
-                self.buffer.chars[row][col].write(character);
-                self.column_position += 1;
             }
+            // Form feed, a new page
+            0x0c => {
+                (0..self.height()).for_each(|row| self.clear_row(row));
+                self.row_position = 0;
+                self.column_position = 0;
+                self.update_cursor();
+            }
+            0x07 => {
+                if let Some(bell) = self.bell {
+                    bell();
+                }
+            }
+            byte => self.write_glyph(byte),
         }
     }
 
+    /*
+     * What to do for the bell character, e.g. beep or flash something. It
+     * runs with the console locked, so it must not print. The consoles beep on
+     * the PC speaker, see speaker.rs.
+     */
+    pub fn set_bell(&mut self, bell: Option<fn()>) {
+        self.bell = bell;
+    }
+
+    /*
+     * Put a character on the screen without looking at it. All 256 values are
+     * glyphs of code page 437, including the ones that are control characters
+     * in ASCII, e.g. 0x0a draws "◙" here instead of starting a new line.
+     */
+    fn write_glyph(&mut self, byte: u8) {
+        if self.column_position >= self.width() {
+            self.new_line();
+        }
+        let row = self.row_position;
+        let col = self.column_position;
+        let color_code = self.color_code;
+        let character = ScreenChar {
+            ascii_character: byte,
+            color_code,
+        };
+
+        /*
+         * Equivalent to self.buffer.chars[row][col] = character;
+         * On the VGA buffer this turns into a volatile write, we don't want
+         * this stuff to get compiled out
+         */
+        self.buffer.write(row, col, character);
+        self.column_position += 1;
+        self.update_cursor();
+    }
+
     fn new_line(&mut self) {
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let character = self.buffer.chars[row][col].read();
-                self.buffer.chars[row - 1][col].write(character);
-            }
+        // Cursor was moved up, there is still room below it
+        if self.row_position < self.height() - 1 {
+            self.row_position += 1;
+            self.column_position = 0;
+            self.update_cursor();
+            return;
         }
-        self.clear_row(BUFFER_HEIGHT - 1);
+        let top = self.read_row(0);
+        self.scrollback.push(top);
+        self.scroll_rows(0..self.height(), 0..self.width());
+        self.clear_row(self.height() - 1);
         self.column_position = 0;
+        self.update_cursor();
+    }
+
+    /*
+     * Move the rows up by one, only touching the given columns. The first row
+     * is lost and the last one still has its old content, it's up to the
+     * caller to clear it.
+     */
+    fn scroll_rows(&mut self, rows: Range<usize>, columns: Range<usize>) {
+        for row in rows.start + 1..rows.end {
+            for col in columns.clone() {
+                let character = self.buffer.read(row, col);
+                self.buffer.write(row - 1, col, character);
+            }
+        }
+    }
+
+    /*
+     * Move the hardware cursor to where the next character will be written.
+     * Once a row is full the column position is one past the last cell, until
+     * the next character wraps we keep the cursor on the last cell.
+     */
+    fn update_cursor(&mut self) {
+        /*
+         * When scrolled back the cursor moves down with the rest of the live
+         * screen. If that is below the bottom, we park it on an offset past the
+         * end of the screen which makes it disappear.
+         */
+        let row = (self.row_position + self.scroll_offset).min(self.height());
+        let col = self.column_position.min(self.width() - 1);
+        self.buffer.move_cursor(row, col);
+    }
+
+    fn read_row(&self, row: usize) -> scrollback::Row {
+        self.buffer.chars[row]
+    }
+
+    fn write_row(&mut self, row: usize, chars: &scrollback::Row) {
+        for (col, &character) in chars.iter().enumerate().take(self.width()) {
+            self.buffer.write(row, col, character);
+        }
+    }
+
+    /*
+     * Look at older output. The live screen is saved away the first time we
+     * leave it, so that it can be put back once we return to the bottom.
+     */
+    pub fn scroll_up(&mut self, lines: usize) {
+        let offset = (self.scroll_offset + lines).min(self.scrollback.len());
+        if offset == self.scroll_offset {
+            return;
+        }
+        if self.scroll_offset == 0 {
+            for row in 0..self.height() {
+                self.live_rows[row] = self.read_row(row);
+            }
+        }
+        self.scroll_offset = offset;
+        self.redraw_view();
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        if self.scroll_offset == 0 {
+            return;
+        }
+        self.scroll_offset = self.scroll_offset.saturating_sub(lines);
+        self.redraw_view();
+    }
+
+    // Go back to the live screen, new output always lands there
+    pub fn scroll_to_bottom(&mut self) {
+        self.scroll_down(self.scroll_offset);
+    }
+
+    /*
+     * The view is the last scroll_offset rows of the scrollback followed by the
+     * top of the live screen.
+     */
+    fn redraw_view(&mut self) {
+        let history = self.scrollback.len();
+        for row in 0..self.height() {
+            let line = history - self.scroll_offset + row;
+            let chars = if line < history {
+                *self.scrollback.get(line)
+            } else {
+                self.live_rows[line - history]
+            };
+            self.write_row(row, &chars);
+        }
+        self.update_cursor();
+    }
+
+    pub fn show_cursor(&mut self) {
+        self.cursor_visible = true;
+        let (start, end) = self.cursor_shape;
+        self.buffer.enable_cursor(start, end);
+        self.update_cursor();
+    }
+
+    pub fn hide_cursor(&mut self) {
+        self.cursor_visible = false;
+        self.buffer.disable_cursor();
+    }
+
+    // Put this console on the screen, including its cursor
+    fn activate(&mut self, hardware: &'static mut S) {
+        self.buffer.attach(hardware);
+        self.restore_cursor();
+    }
+
+    // The same, but the screen keeps what is on it, see init()
+    fn adopt(&mut self, hardware: &'static mut S) {
+        self.buffer.adopt(hardware);
+        self.restore_cursor();
+    }
+
+    fn restore_cursor(&mut self) {
+        if self.cursor_visible {
+            self.show_cursor();
+        } else {
+            self.hide_cursor();
+        }
+    }
+
+    /*
+     * The cursor covers the scanlines start..=end of the character cell,
+     * e.g. (14, 15) for an underline or (0, 15) for a full block.
+     * Setting the shape also makes the cursor visible.
+     */
+    pub fn set_cursor_shape(&mut self, start: u8, end: u8) {
+        self.cursor_shape = (start, end);
+        self.show_cursor();
     }
 
     fn clear_row(&mut self, row: usize) {
+        self.clear_columns(row, 0..self.width());
+    }
+
+    fn clear_columns(&mut self, row: usize, columns: Range<usize>) {
+        self.fill_columns(row, columns, self.color_code);
+    }
+
+    fn fill_columns(&mut self, row: usize, columns: Range<usize>, color_code: ColorCode) {
         let blank = ScreenChar {
             ascii_character: b' ',
-            color_code: self.color_code,
+            color_code,
         };
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for col in columns {
+            self.buffer.write(row, col, blank);
+        }
+    }
+
+    /*
+     * Put a string at a fixed place on the screen, e.g. for a status line.
+     * The writer's own position is left alone and nothing wraps or scrolls,
+     * whatever doesn't fit into the row is cut off.
+     */
+    pub fn write_at(&mut self, row: usize, col: usize, s: &str) {
+        if row >= self.height() {
+            return;
+        }
+        self.scroll_to_bottom();
+        for (col, c) in (col..self.width()).zip(s.chars()) {
+            let character = ScreenChar {
+                ascii_character: cp437::from_char(c).unwrap_or(0xfe),
+                color_code: self.color_code,
+            };
+            self.buffer.write(row, col, character);
         }
     }
 
+    /*
+     * Write into a part of the screen instead of the whole of it, see
+     * region.rs. E.g. with a header on top of a log pane:
+     *
+     *     let mut header = Region::new(0, 0, 1, 80, Color::Black, Color::Cyan);
+     *     let mut log = Region::new(1, 0, 24, 80, ...);
+     *     with_writer(|writer| write!(writer.region(&mut log), "..."))
+     */
+    pub fn region<'a>(&'a mut self, region: &'a mut Region) -> RegionWriter<'a, S> {
+        RegionWriter::new(self, region)
+    }
+
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    // Back to the colors the console started out with
+    pub fn reset_color(&mut self) {
+        self.color_code = self.default_color;
+    }
+
+    /*
+     * Run f with different colors and put the old ones back afterwards, even
+     * if they were changed by an escape sequence in between.
+     */
+    pub fn with_color<F>(&mut self, foreground: Color, background: Color, f: F)
+    where
+        F: FnOnce(&mut Writer<S>),
+    {
+        let color_code = self.color_code;
+        self.set_color(foreground, background);
+        f(self);
+        self.color_code = color_code;
+    }
+
+    /*
+     * Rust strings are UTF-8, while the VGA only knows the 256 characters of
+     * code page 437. Every character that has a glyph there gets translated,
+     * e.g. "é" or "═", and only the rest becomes "■".
+     */
     pub fn write_string(&mut self, s: &str) {
-        for byte in s.bytes() {
-            match byte {
-                // print-able ASCII byte or newline
-                0x20..=0x7e | b'\n' => self.write_byte(byte),
-                // Not part for print-able ASCII range. Write "■" (0xfe)
-                _ => self.write_byte(0xfe),
+        self.scroll_to_bottom();
+        for c in s.chars() {
+            match self.parser.advance(c) {
+                ansi::Action::None => {}
+                ansi::Action::Csi(sequence) => self.execute_csi(&sequence),
+                ansi::Action::Print(c @ ('\n' | '\r' | '\t' | '\x08' | '\x0c' | '\x07')) => {
+                    self.write_byte(c as u8)
+                }
+                // Not part of code page 437. Write "■" (0xfe)
+                ansi::Action::Print(c) => self.write_glyph(cp437::from_char(c).unwrap_or(0xfe)),
             }
         }
     }
+
+    /*
+     * Act on a complete escape sequence. Unknown sequences are ignored, like
+     * a real terminal would do.
+     */
+    fn execute_csi(&mut self, sequence: &ansi::CsiSequence) {
+        let n = sequence.param_or(0, 1) as usize;
+        let col = self.column_position.min(self.width() - 1);
+        match sequence.command {
+            // Cursor up, down, forward and back
+            b'A' => self.row_position = self.row_position.saturating_sub(n),
+            b'B' => self.row_position = (self.row_position + n).min(self.height() - 1),
+            b'C' => self.column_position = (col + n).min(self.width() - 1),
+            b'D' => self.column_position = col.saturating_sub(n),
+            // Cursor position, rows and columns are counted from 1
+            b'H' | b'f' => {
+                let row = sequence.param_or(0, 1) as usize;
+                let col = sequence.param_or(1, 1) as usize;
+                self.row_position = row.min(self.height()) - 1;
+                self.column_position = col.min(self.width()) - 1;
+            }
+            // Erase in display: 0 = to the end, 1 = to the start, 2 = all
+            b'J' => {
+                let row = self.row_position;
+                match sequence.param_or(0, 0) {
+                    0 => {
+                        self.clear_columns(row, col..self.width());
+                        (row + 1..self.height()).for_each(|row| self.clear_row(row));
+                    }
+                    1 => {
+                        (0..row).for_each(|row| self.clear_row(row));
+                        self.clear_columns(row, 0..col + 1);
+                    }
+                    _ => (0..self.height()).for_each(|row| self.clear_row(row)),
+                }
+            }
+            // Erase in line, same parameters as erase in display
+            b'K' => {
+                let row = self.row_position;
+                match sequence.param_or(0, 0) {
+                    0 => self.clear_columns(row, col..self.width()),
+                    1 => self.clear_columns(row, 0..col + 1),
+                    _ => self.clear_row(row),
+                }
+            }
+            b'm' => {
+                self.color_code = self
+                    .color_code
+                    .select_graphic_rendition(sequence.params(), self.default_color)
+            }
+            _ => {}
+        }
+        self.update_cursor();
+    }
 }
 
-impl fmt::Write for Writer {
+impl<S: TextSurface> fmt::Write for Writer<S> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         self.write_string(s);
         Ok(())
@@ -135,29 +742,402 @@ impl fmt::Write for Writer {
 
 /*
  * A global interface for writing.
- * The problem is that statics need to have all the values like compile-time but
- * we do not, example being the pointer to the buffer is at run time and hence
- * all the functions must be made const. This is bad and not should not be done.
- *
- * We need:- one-time initialization of statics with non-const functions.
- * Sol: lazy_static
- * This makes initializations at runtime but right at the beginning, therefore
- * making complex initializations easy.
- * Here, the problem is though it is immutable to make it mutable everything
- * else must be under unsafe again, which is again undesirable.
+ * A Writer is big, mostly because of its scrollback, so the consoles are
+ * built at compile time with const functions and live in a plain static.
+ * Built at runtime, e.g. with lazy_static, they would be put together on the
+ * stack first, and the first print! would need over a megabyte of it. The
+ * catch is that the pointer to the VGA buffer can't be part of a static, it
+ * is handed to console 0 in init().
  *
  * To get interior synchronized mutability we use spinlocks (Not mutexes
  * because we don't have the concept of threads and blocking yet in our kernel)
  */
-use lazy_static::lazy_static;
-use spin::Mutex;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, Once};
+use x86_64::instructions::interrupts;
 
-lazy_static! {
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+// What a console holds before any output got there
+const BLANK: ScreenChar = ScreenChar {
+    ascii_character: b' ',
+    color_code: ColorCode::new(Color::Yellow, Color::Black),
+};
+
+/*
+ * Virtual consoles: the kernel log, a shell and a debug monitor can each get
+ * their own screen, and we flip between them with switch_console().
+ * Console 0 is the one that is on the screen at boot.
+ */
+pub const NUM_CONSOLES: usize = 3;
+
+// The BIOS hands over the screen in 80x25
+const BOOT_MODE: TextMode = TextMode::Text80x25;
+
+/*
+ * The consoles are only locked with interrupts off, see _print(). That's why
+ * the mutexes are private and everybody else goes through with_writer() and
+ * with_console().
+ */
+static CONSOLES: [Mutex<Writer>; NUM_CONSOLES] = [console(), console(), console()];
+
+// The kernel log console, print! writes here
+static WRITER: &Mutex<Writer> = &CONSOLES[0];
+
+/*
+ * Run f on the kernel log console, with interrupts off for as long as it is
+ * locked. f must not print!, that would wait for the lock it holds.
+ */
+pub fn with_writer<R>(f: impl FnOnce(&mut Writer) -> R) -> R {
+    interrupts::without_interrupts(|| f(&mut WRITER.lock()))
+}
+
+// The same for any of the consoles, e.g. the one active_console() is showing
+pub fn with_console<R>(index: usize, f: impl FnOnce(&mut Writer) -> R) -> R {
+    assert!(index < NUM_CONSOLES, "no virtual console {}", index);
+    interrupts::without_interrupts(|| f(&mut CONSOLES[index].lock()))
+}
+
+// The consoles beep for "\x07"
+const fn console() -> Mutex<Writer> {
+    let mut writer = Writer::new(ConsoleBuffer::off_screen(BOOT_MODE), BOOT_MODE);
+    writer.bell = Some(speaker::beep);
+    Mutex::new(writer)
+}
+
+/*
+ * Put console 0 on the screen, keeping what the BIOS and the bootloader left
+ * there. Until then print! only goes into the console's own copy of the
+ * screen (and kmsg), so this comes first in crate::init().
+ */
+pub fn init() {
+    interrupts::without_interrupts(|| {
+        let _active = ACTIVE_CONSOLE.lock();
+        let mut writer = WRITER.lock();
+        if !writer.buffer.is_visible() {
+            writer.adopt(unsafe { &mut *(0xb8000 as *mut Buffer) });
+        }
+    })
+}
+
+static ACTIVE_CONSOLE: Mutex<usize> = Mutex::new(0);
+
+pub fn active_console() -> usize {
+    *ACTIVE_CONSOLE.lock()
+}
+
+/*
+ * The VGA buffer is taken away from the console that is on the screen and
+ * given to the new one. ACTIVE_CONSOLE stays locked the whole time so that
+ * two switches cannot run into each other.
+ * In graphics mode no console has the VGA buffer, the new one only gets it
+ * once we are back in text mode.
+ */
+pub fn switch_console(index: usize) {
+    interrupts::without_interrupts(|| {
+        assert!(index < NUM_CONSOLES, "no virtual console {}", index);
+        let mut active = ACTIVE_CONSOLE.lock();
+        if *active == index {
+            return;
+        }
+        if let Some(hardware) = CONSOLES[*active].lock().buffer.detach() {
+            CONSOLES[index].lock().activate(hardware);
+        }
+        *active = index;
+    })
+}
+
+static TEXT_MODE: Mutex<TextMode> = Mutex::new(BOOT_MODE);
+
+// The font of the 8 scanline modes, the BIOS only comes with a 16 scanline one
+static FONT_8X8: &[u8] = include_bytes!("../fonts/misc-fixed-8x8.psf");
+
+// The BIOS font, saved before we overwrite it for the first time
+static BIOS_FONT: Once<[u8; registers::FONT_GLYPHS * 16]> = Once::new();
+
+pub fn text_mode() -> TextMode {
+    *TEXT_MODE.lock()
+}
+
+/*
+ * Has to be called before anything touches the font for the first time, i.e.
+ * before any mode switch. Later calls just return the saved copy.
+ */
+fn bios_font() -> &'static [u8] {
+    BIOS_FONT.call_once(|| {
+        let mut font = [0; registers::FONT_GLYPHS * 16];
+        registers::read_font(&mut font, 16);
+        font
+    })
+}
+
+// Program the registers for a text mode and load the font that goes with it
+fn program_text_mode(mode: TextMode) {
+    registers::write_mode(mode.registers());
+    load_palette(&PALETTE.lock());
+    load_blink(BLINK.load(Ordering::Relaxed));
+    load_text_font(mode);
+}
+
+/*
+ * A font loaded with load_font() wins over the built-in ones, but only in the
+ * modes whose character cells are as high as its glyphs.
+ */
+fn load_text_font(mode: TextMode) {
+    let custom_font = CUSTOM_FONT
+        .lock()
+        .filter(|font| font.height() == mode.font_height());
+    if let Some(font) = custom_font {
+        registers::write_glyphs(0, font.bitmaps(), font.height());
+    } else if mode.font_height() == 16 {
+        registers::write_glyphs(0, bios_font(), 16);
+    } else {
+        let font = psf::Font::parse(FONT_8X8)
+            .filter(|font| font.width() == 8)
+            .expect("broken 8x8 font");
+        registers::write_glyphs(0, font.bitmaps(), font.height());
+    }
+    // The glyphs of set_glyph() go on top of whatever font that was
+    for (index, glyph) in GLYPHS.lock().iter().enumerate() {
+        if let Some(glyph) = glyph {
+            registers::write_glyphs(index, glyph.scanlines(), glyph.height);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontError {
+    // Not a PSF font, or one that is cut short
+    Invalid,
+    // The VGA draws the 9th column on its own, fonts are 8 pixels wide
+    Width(usize),
+    // The character generator has room for 32 scanlines per glyph
+    Height(usize),
+}
+
+static CUSTOM_FONT: Mutex<Option<psf::Font<'static>>> = Mutex::new(None);
+
+// A glyph that set_glyph() drew differently
+#[derive(Clone, Copy)]
+struct Glyph {
+    bitmap: [u8; registers::FONT_GLYPH_STRIDE],
+    height: usize,
+}
+
+impl Glyph {
+    fn scanlines(&self) -> &[u8] {
+        &self.bitmap[..self.height]
+    }
+}
+
+const NO_GLYPH: Option<Glyph> = None;
+
+static GLYPHS: Mutex<[Option<Glyph>; registers::FONT_GLYPHS]> =
+    Mutex::new([NO_GLYPH; registers::FONT_GLYPHS]);
+
+// Is the active console on the screen, i.e. are we in text mode?
+fn in_text_mode(active: usize) -> bool {
+    CONSOLES[active].lock().buffer.is_visible()
+}
+
+/*
+ * Use the glyphs of a PSF font instead of the built-in font. The font is used
+ * in every text mode with the same character height, e.g. an 8x16 font in
+ * 80x25 and 90x30, so it can be loaded before switching to such a mode.
+ * It is meant for fonts that are part of the kernel, e.g.
+ * load_font(include_bytes!("../fonts/misc-fixed-8x16.psf")).
+ */
+pub fn load_font(data: &'static [u8]) -> Result<(), FontError> {
+    let font = psf::Font::parse(data).ok_or(FontError::Invalid)?;
+    if font.width() != 8 {
+        return Err(FontError::Width(font.width()));
+    }
+    if font.height() > registers::FONT_GLYPH_STRIDE {
+        return Err(FontError::Height(font.height()));
+    }
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let text_mode = TEXT_MODE.lock();
+        *CUSTOM_FONT.lock() = Some(font);
+        if in_text_mode(*active) {
+            bios_font();
+            load_text_font(*text_mode);
+        }
     });
+    Ok(())
+}
+
+// Back to the BIOS font and our 8x8 one, without the glyphs of set_glyph()
+pub fn reset_font() {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let text_mode = TEXT_MODE.lock();
+        *CUSTOM_FONT.lock() = None;
+        *GLYPHS.lock() = [NO_GLYPH; registers::FONT_GLYPHS];
+        if in_text_mode(*active) {
+            bios_font();
+            load_text_font(*text_mode);
+        }
+    })
+}
+
+/*
+ * Draw the character with the code index differently, e.g. to have the
+ * pieces of a progress bar or a few icons. bitmap has one byte for every
+ * scanline, the highest bit is the leftmost pixel.
+ * The glyph stays until reset_font(): it is drawn again whenever the font is
+ * loaded, on every change of the text mode, after load_font() and when coming
+ * back from a graphics mode. In a graphics mode it only gets drawn then.
+ */
+pub fn set_glyph(index: u8, bitmap: &[u8]) {
+    assert!(
+        !bitmap.is_empty() && bitmap.len() <= registers::FONT_GLYPH_STRIDE,
+        "a glyph has 1 to {} scanlines",
+        registers::FONT_GLYPH_STRIDE
+    );
+    let mut glyph = Glyph {
+        bitmap: [0; registers::FONT_GLYPH_STRIDE],
+        height: bitmap.len(),
+    };
+    glyph.bitmap[..bitmap.len()].copy_from_slice(bitmap);
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let _text_mode = TEXT_MODE.lock();
+        GLYPHS.lock()[usize::from(index)] = Some(glyph);
+        if in_text_mode(*active) {
+            bios_font();
+            registers::write_glyphs(usize::from(index), glyph.scanlines(), glyph.height);
+        }
+    })
+}
+
+// The scanlines of a character as the VGA has them now, None in a graphics mode
+pub fn glyph(index: u8) -> Option<[u8; registers::FONT_GLYPH_STRIDE]> {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let _text_mode = TEXT_MODE.lock();
+        if !in_text_mode(*active) {
+            return None;
+        }
+        Some(registers::read_glyph(usize::from(index)))
+    })
+}
+
+/*
+ * Switch the VGA to another text mode. All the consoles are resized to the
+ * new mode, the one on the screen is drawn again afterwards.
+ * In graphics mode we only take note of the new mode, it gets programmed
+ * when we go back to text mode.
+ */
+pub fn set_text_mode(mode: TextMode) {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let mut text_mode = TEXT_MODE.lock();
+        if *text_mode == mode {
+            return;
+        }
+        let hardware = CONSOLES[*active].lock().buffer.detach();
+        if hardware.is_some() {
+            bios_font();
+            program_text_mode(mode);
+        }
+        *text_mode = mode;
+
+        for console in CONSOLES.iter() {
+            console.lock().resize(mode);
+        }
+        if let Some(hardware) = hardware {
+            CONSOLES[*active].lock().activate(hardware);
+        }
+    })
+}
+
+/*
+ * The color of a character goes through two tables before it is shown. The
+ * attribute controller turns it into one of the 256 entries of the DAC, and
+ * that entry holds the red, green and blue that end up on the screen. Out of
+ * the box color 6 is DAC entry 0x14 and colors 8 to 15 are 0x38 to 0x3f, so
+ * we point every color at the DAC entry with its own number and set that.
+ */
+static PALETTE: Mutex<[[u8; 3]; 16]> = Mutex::new(DEFAULT_PALETTE);
+
+// The DAC only has 6 bits per color, the palette is kept with 8
+fn load_palette(palette: &[[u8; 3]; 16]) {
+    for (color, &rgb) in palette.iter().enumerate() {
+        registers::set_attribute(color as u8, color as u8);
+        registers::write_dac(color as u8, rgb.map(|level| level >> 2));
+    }
+}
+
+/*
+ * Show color as the given red, green and blue (0 to 255 each) from now on,
+ * on every console and in every text mode. The framebuffer console has no
+ * palette of its own, there only what is drawn afterwards changes color.
+ */
+pub fn set_palette(color: Color, rgb: [u8; 3]) {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let _text_mode = TEXT_MODE.lock();
+        let mut palette = PALETTE.lock();
+        palette[color as usize] = rgb;
+        if in_text_mode(*active) {
+            load_palette(&palette);
+        }
+        if let Some(framebuffer) = framebuffer::FRAMEBUFFER.lock().as_mut() {
+            framebuffer.set_palette(*palette);
+        }
+    })
+}
+
+pub fn reset_palette() {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let _text_mode = TEXT_MODE.lock();
+        let mut palette = PALETTE.lock();
+        *palette = DEFAULT_PALETTE;
+        if in_text_mode(*active) {
+            load_palette(&palette);
+        }
+        if let Some(framebuffer) = framebuffer::FRAMEBUFFER.lock().as_mut() {
+            framebuffer.set_palette(*palette);
+        }
+    })
+}
+
+// The 16 colors as set_palette() left them
+pub fn palette() -> [[u8; 3]; 16] {
+    interrupts::without_interrupts(|| *PALETTE.lock())
+}
+
+/*
+ * The top bit of the attribute byte is either "blink" or the bright bit of
+ * the background color, bit 3 of the attribute mode control register picks
+ * which. The BIOS turns blinking on, so by default there are only 8
+ * background colors.
+ */
+const ATTRIBUTE_MODE_CONTROL: u8 = 0x10;
+const ATTRIBUTE_BLINK: u8 = 1 << 3;
+
+static BLINK: AtomicBool = AtomicBool::new(true);
+
+fn load_blink(enabled: bool) {
+    let mode_control = registers::read_attribute(ATTRIBUTE_MODE_CONTROL);
+    let mode_control = if enabled {
+        mode_control | ATTRIBUTE_BLINK
+    } else {
+        mode_control & !ATTRIBUTE_BLINK
+    };
+    registers::set_attribute(ATTRIBUTE_MODE_CONTROL, mode_control);
+}
+
+// false gives all 16 background colors instead of blinking text
+pub fn set_blink(enabled: bool) {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        let _text_mode = TEXT_MODE.lock();
+        BLINK.store(enabled, Ordering::Relaxed);
+        if in_text_mode(*active) {
+            load_blink(enabled);
+        }
+    })
 }
 
 /* Define our own print and println! macros.
@@ -174,8 +1154,337 @@ macro_rules! println {
     ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
 }
 
+/*
+ * Same thing with colors, e.g.
+ * println_colored!(Color::LightRed, Color::Black, "error: {}", err);
+ */
+#[macro_export]
+macro_rules! print_colored {
+    ($fg:expr, $bg:expr, $($arg:tt)*) => (
+        $crate::vga_buffer::_print_colored($fg, $bg, format_args!($($arg)*))
+    );
+}
+
+#[macro_export]
+macro_rules! println_colored {
+    ($fg:expr, $bg:expr) => ($crate::print_colored!($fg, $bg, "\n"));
+    ($fg:expr, $bg:expr, $($arg:tt)*) => (
+        $crate::print_colored!($fg, $bg, "{}\n", format_args!($($arg)*))
+    );
+}
+
+/*
+ * Both go to the framebuffer console instead once there is one, and to the
+ * kernel message ring either way.
+ * Interrupts are off while we hold the lock: an interrupt handler that prints
+ * would otherwise spin forever on a lock that we can't give back until the
+ * handler returns. The same goes for everything else that locks a console.
+ */
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
+    interrupts::without_interrupts(|| {
+        kmsg::_record(args);
+        write_screen(args);
+    });
+}
+
+#[doc(hidden)]
+pub fn _print_colored(foreground: Color, background: Color, args: fmt::Arguments) {
+    interrupts::without_interrupts(|| {
+        kmsg::_record(args);
+        write_screen_colored(foreground, background, args);
+    });
+}
+
+/*
+ * print! and print_colored! without the kernel message ring, for text that
+ * is kept there some other way or not at all, see logger.rs.
+ */
+pub fn show(args: fmt::Arguments) {
+    interrupts::without_interrupts(|| write_screen(args));
+}
+
+pub fn show_colored(foreground: Color, background: Color, args: fmt::Arguments) {
+    interrupts::without_interrupts(|| write_screen_colored(foreground, background, args));
+}
+
+/*
+ * print! for the handlers of the exceptions we return from, see
+ * interrupts/exceptions.rs. They can come in while the code they interrupt
+ * holds a lock of print!, an NMI even with interrupts off, and waiting for
+ * it would mean waiting forever. So if any of them is taken nothing is
+ * printed and the caller gets false.
+ */
+pub fn try_print(args: fmt::Arguments) -> bool {
+    use core::fmt::Write;
+    interrupts::without_interrupts(|| {
+        let mut framebuffer = match framebuffer::FRAMEBUFFER.try_lock() {
+            Some(framebuffer) => framebuffer,
+            None => return false,
+        };
+        if let Some(framebuffer) = framebuffer.as_mut() {
+            if !kmsg::try_record(args) {
+                return false;
+            }
+            framebuffer.write_fmt(args).unwrap();
+            return true;
+        }
+        let mut writer = match WRITER.try_lock() {
+            Some(writer) => writer,
+            None => return false,
+        };
+        if !kmsg::try_record(args) {
+            return false;
+        }
+        writer.write_fmt(args).unwrap();
+        true
+    })
+}
+
+fn write_screen(args: fmt::Arguments) {
     use core::fmt::Write;
+    if let Some(framebuffer) = framebuffer::FRAMEBUFFER.lock().as_mut() {
+        framebuffer.write_fmt(args).unwrap();
+        return;
+    }
     WRITER.lock().write_fmt(args).unwrap();
 }
+
+fn write_screen_colored(foreground: Color, background: Color, args: fmt::Arguments) {
+    use core::fmt::Write;
+    if let Some(framebuffer) = framebuffer::FRAMEBUFFER.lock().as_mut() {
+        framebuffer.with_color(foreground, background, |writer| {
+            writer.write_fmt(args).unwrap()
+        });
+        return;
+    }
+    WRITER.lock().with_color(foreground, background, |writer| {
+        writer.write_fmt(args).unwrap()
+    });
+}
+
+/*
+ * The screen's part of crate::force_unlock(). The panic can have hit us while some
+ * lock on the way to the screen was held, by the code that panicked or by the
+ * code it interrupted, and nobody is going to give it back. So we break all
+ * of them open and make sure that WRITER is on the screen, in text mode,
+ * whatever was going on before. print! and println! work again afterwards.
+ *
+ * Unsafe because whoever held a lock may still be in the middle of using it,
+ * only call this when that code will never run again.
+ */
+pub unsafe fn force_unlock() {
+    interrupts::disable();
+    framebuffer::FRAMEBUFFER.force_unlock();
+    ACTIVE_CONSOLE.force_unlock();
+    TEXT_MODE.force_unlock();
+    PALETTE.force_unlock();
+    CUSTOM_FONT.force_unlock();
+    GLYPHS.force_unlock();
+    for console in CONSOLES.iter() {
+        console.force_unlock();
+    }
+    if framebuffer::FRAMEBUFFER.lock().is_some() {
+        return;
+    }
+
+    let mut active = ACTIVE_CONSOLE.lock();
+    let hardware = match CONSOLES[*active].lock().buffer.detach() {
+        Some(hardware) => hardware,
+        /*
+         * Nobody has the VGA buffer, we are in a graphics mode or halfway
+         * through a mode switch. Start over with the text mode.
+         */
+        None => {
+            program_text_mode(*TEXT_MODE.lock());
+            &mut *(0xb8000 as *mut Buffer)
+        }
+    };
+    *active = 0;
+    WRITER.lock().activate(hardware);
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+mod tests {
+    use super::*;
+
+    // A console on a screen of its own, which lives as long as the test
+    fn writer(mode: TextMode) -> Writer<MemoryBuffer> {
+        let surface = Box::leak(Box::new(MemoryBuffer::new()));
+        let mut writer = Writer::new(ConsoleBuffer::off_screen(mode), mode);
+        writer.buffer.adopt(surface);
+        writer
+    }
+
+    // What the surface shows in a row, without the blanks at the end
+    fn row_text(writer: &Writer<MemoryBuffer>, row: usize) -> String {
+        let surface = writer.buffer.hardware.as_ref().unwrap();
+        let text: String = (0..writer.width())
+            .map(|col| surface.read(row * writer.width() + col).ascii_character as char)
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    fn cursor(writer: &Writer<MemoryBuffer>) -> Option<usize> {
+        writer.buffer.hardware.as_ref().unwrap().cursor()
+    }
+
+    #[test]
+    fn writes_to_the_last_row() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("hello");
+        assert_eq!(row_text(&writer, 24), "hello");
+        assert_eq!(row_text(&writer, 23), "");
+    }
+
+    #[test]
+    fn new_line_scrolls_up() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("first\nsecond\n");
+        assert_eq!(row_text(&writer, 22), "first");
+        assert_eq!(row_text(&writer, 23), "second");
+        assert_eq!(row_text(&writer, 24), "");
+    }
+
+    #[test]
+    fn long_lines_wrap() {
+        let mut writer = writer(TextMode::Text80x25);
+        let line = "x".repeat(80);
+        writer.write_string(&line);
+        // A full row doesn't scroll until there is more to write
+        assert_eq!(row_text(&writer, 24), line);
+        writer.write_string("y");
+        assert_eq!(row_text(&writer, 23), line);
+        assert_eq!(row_text(&writer, 24), "y");
+    }
+
+    #[test]
+    fn wraps_at_the_width_of_the_mode() {
+        let mut writer = writer(TextMode::Text90x30);
+        writer.write_string(&"x".repeat(91));
+        assert_eq!(row_text(&writer, 28), "x".repeat(90));
+        assert_eq!(row_text(&writer, 29), "x");
+    }
+
+    #[test]
+    fn scrollback_keeps_old_rows() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.show_cursor();
+        writer.write_string("oldest\n");
+        for _ in 0..25 {
+            writer.write_string("\n");
+        }
+        assert!((0..25).all(|row| row_text(&writer, row) != "oldest"));
+        writer.scroll_up(2);
+        assert_eq!(row_text(&writer, 0), "oldest");
+        // Parked below the screen while scrolled back
+        assert_eq!(cursor(&writer), Some(25 * 80));
+        writer.scroll_to_bottom();
+        assert_eq!(row_text(&writer, 0), "");
+    }
+
+    #[test]
+    fn control_characters() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("abc\rx");
+        assert_eq!(row_text(&writer, 24), "xbc");
+        writer.write_string("\ty");
+        assert_eq!(row_text(&writer, 24), "xbc     y");
+        writer.write_string("\x08\x08z");
+        assert_eq!(row_text(&writer, 24), "xbc    z");
+        writer.write_string("\x0cpage");
+        assert_eq!(row_text(&writer, 0), "page");
+        assert_eq!(row_text(&writer, 24), "");
+    }
+
+    #[test]
+    fn escape_sequences_set_colors() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("\x1b[31;44mred\x1b[0m.");
+        let surface = writer.buffer.hardware.as_ref().unwrap();
+        let red = ColorCode::new(Color::Red, Color::Blue);
+        assert_eq!(surface.read(24 * 80).color_code, red);
+        assert_eq!(surface.read(24 * 80 + 3).color_code, writer.default_color);
+    }
+
+    #[test]
+    fn unicode_is_translated_to_code_page_437() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("é─\u{1F600}");
+        let surface = writer.buffer.hardware.as_ref().unwrap();
+        let glyphs: Vec<u8> = (0..3)
+            .map(|col| surface.read(24 * 80 + col).ascii_character)
+            .collect();
+        assert_eq!(glyphs, [0x82, 0xc4, 0xfe]);
+    }
+
+    #[test]
+    fn cursor_follows_the_text() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.show_cursor();
+        writer.write_string("abc");
+        assert_eq!(cursor(&writer), Some(24 * 80 + 3));
+        writer.hide_cursor();
+        assert_eq!(cursor(&writer), None);
+    }
+
+    #[test]
+    fn regions_scroll_on_their_own() {
+        let mut writer = writer(TextMode::Text80x25);
+        writer.write_string("outside");
+        let mut region = Region::new(0, 10, 2, 5, Color::White, Color::Black);
+        writer.region(&mut region).write_string("one\ntwo\nthree");
+        assert_eq!(row_text(&writer, 0), "          two");
+        assert_eq!(row_text(&writer, 1), "          three");
+        assert_eq!(row_text(&writer, 24), "outside");
+    }
+
+    #[test]
+    fn regions_survive_a_smaller_mode() {
+        let mut writer = writer(TextMode::Text80x50);
+        let mut region = Region::new(40, 0, 10, 5, Color::White, Color::Black);
+        writer.resize(TextMode::Text80x25);
+        writer.write_string("live");
+        // The region is below the smaller screen, nothing of it is drawn
+        writer.region(&mut region).write_string("a\nb");
+        assert_eq!(row_text(&writer, 24), "live");
+        writer.resize(TextMode::Text80x50);
+        // Its cursor is still where the text left it
+        writer.region(&mut region).write_string("c");
+        assert_eq!(row_text(&writer, 41), " c");
+        let mut region_writer = writer.region(&mut region);
+        region_writer.clear();
+        region_writer.write_string("1\n2\n3\n4\n5\n6\n7\n8\n9\n10");
+        assert_eq!(row_text(&writer, 40), "1");
+        assert_eq!(row_text(&writer, 49), "10");
+    }
+
+    #[test]
+    fn regions_are_cut_off_at_the_screen_edge() {
+        let mut writer = writer(TextMode::Text80x25);
+        let mut region = Region::new(22, 78, 5, 4, Color::White, Color::Black);
+        writer
+            .region(&mut region)
+            .write_string("abcd\nefgh\nijkl\nmnop");
+        assert_eq!(row_text(&writer, 22), format!("{:78}ab", ""));
+        assert_eq!(row_text(&writer, 23), format!("{:78}ef", ""));
+        assert_eq!(row_text(&writer, 24), format!("{:78}ij", ""));
+    }
+
+    #[test]
+    fn bell_writes_nothing() {
+        static RUNG: AtomicBool = AtomicBool::new(false);
+        fn ring() {
+            RUNG.store(true, Ordering::Relaxed);
+        }
+        let mut writer = writer(TextMode::Text80x25);
+        writer.show_cursor();
+        writer.write_string("ab\x07");
+        assert_eq!(row_text(&writer, 24), "ab");
+        assert_eq!(cursor(&writer), Some(24 * 80 + 2));
+        writer.set_bell(Some(ring));
+        writer.write_string("\x07c");
+        assert!(RUNG.load(Ordering::Relaxed));
+        assert_eq!(row_text(&writer, 24), "abc");
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * A small parser for the ANSI/VT100 escape sequences that terminals
+ * understand. We only care about the Control Sequence Introducer (CSI)
+ * sequences, which look like this:
+ *
+ *      ESC [ <param> ; <param> ; ... <final byte>
+ *
+ * e.g. "\x1b[31m" (red foreground) or "\x1b[2J" (erase the screen).
+ * A sequence can be split over multiple write_string() calls, that's why the
+ * parser is fed one character at a time and keeps its state in between.
+ * The parser only recognizes the sequences, acting on them is up to the
+ * Writer.
+ */
+use super::Color;
+
+const ESC: char = '\x1b';
+const MAX_PARAMS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    Csi,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CsiSequence {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    pub command: u8,
+}
+
+impl CsiSequence {
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+
+    /*
+     * A missing or zero parameter means "use the default", which is 1 for all
+     * the cursor movement sequences.
+     */
+    pub fn param_or(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+}
+
+pub enum Action {
+    // Nothing to do, the character was part of an escape sequence
+    None,
+    // An ordinary character that should be written out
+    Print(char),
+    // A complete CSI sequence
+    Csi(CsiSequence),
+}
+
+pub struct Parser {
+    state: State,
+    sequence: CsiSequence,
+}
+
+impl Parser {
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            sequence: CsiSequence {
+                params: [0; MAX_PARAMS],
+                len: 0,
+                command: 0,
+            },
+        }
+    }
+
+    pub fn advance(&mut self, c: char) -> Action {
+        match (self.state, c) {
+            // An escape always starts over, even in the middle of a sequence
+            (_, ESC) => {
+                self.state = State::Escape;
+                Action::None
+            }
+            (State::Ground, c) => Action::Print(c),
+            (State::Escape, '[') => {
+                self.state = State::Csi;
+                self.sequence.params = [0; MAX_PARAMS];
+                self.sequence.len = 0;
+                Action::None
+            }
+            // Other escape sequences are not supported, swallow them
+            (State::Escape, _) => {
+                self.state = State::Ground;
+                Action::None
+            }
+            (State::Csi, '0'..='9') => {
+                if self.sequence.len == 0 {
+                    self.sequence.len = 1;
+                }
+                let param = &mut self.sequence.params[self.sequence.len - 1];
+                *param = param
+                    .saturating_mul(10)
+                    .saturating_add((c as u8 - b'0') as u16);
+                Action::None
+            }
+            (State::Csi, ';') => {
+                if self.sequence.len == 0 {
+                    self.sequence.len = 1;
+                }
+                // Parameters beyond MAX_PARAMS get folded into the last one
+                if self.sequence.len < MAX_PARAMS {
+                    self.sequence.len += 1;
+                }
+                Action::None
+            }
+            // Private markers ("\x1b[?25h") and intermediate bytes are ignored
+            (State::Csi, ' '..='?') => Action::None,
+            (State::Csi, '@'..='~') => {
+                self.state = State::Ground;
+                self.sequence.command = c as u8;
+                Action::Csi(self.sequence)
+            }
+            // Anything else is a broken sequence, drop it
+            (State::Csi, _) => {
+                self.state = State::Ground;
+                Action::None
+            }
+        }
+    }
+}
+
+impl Default for Parser {
+    fn default() -> Parser {
+        Parser::new()
+    }
+}
+
+/*
+ * The SGR color numbers follow the order black, red, green, yellow, blue,
+ * magenta, cyan, white. The VGA has its own order (blue is bit 0, red is
+ * bit 2), so we need to map one onto the other.
+ */
+pub fn color(index: u16, bright: bool) -> Color {
+    match (index, bright) {
+        (0, false) => Color::Black,
+        (1, false) => Color::Red,
+        (2, false) => Color::Green,
+        (3, false) => Color::Brown,
+        (4, false) => Color::Blue,
+        (5, false) => Color::Magenta,
+        (6, false) => Color::Cyan,
+        (7, false) => Color::LightGray,
+        (0, true) => Color::DarkGray,
+        (1, true) => Color::LightRed,
+        (2, true) => Color::LightGreen,
+        (3, true) => Color::Yellow,
+        (4, true) => Color::LightBlue,
+        (5, true) => Color::Pink,
+        (6, true) => Color::LightCyan,
+        _ => Color::White,
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * Code page 437 is the character set burnt into the VGA font ROM. The lower
+ * half matches ASCII, except that the control characters have glyphs of their
+ * own (smileys, card suits, arrows). The upper half holds accented letters,
+ * box-drawing characters, some greek letters and math symbols.
+ * The tables below list the Unicode code point of every glyph, the index in
+ * the table is the byte that has to go into the text buffer.
+ */
+
+// Glyphs 0x00..0x1f, 0x00 is an empty cell
+const CONTROL: [char; 32] = [
+    '\0', '☺', '☻', '♥', '♦', '♣', '♠', '•', '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼', //
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨', '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+// Glyphs 0x80..0xff
+const UPPER: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', //
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', //
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', //
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐', //
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧', //
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀', //
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', //
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+/*
+ * Characters that look the same as one of the glyphs above, but have a
+ * different code point, e.g. the greek beta and the german sharp s.
+ */
+const ALIASES: [(char, u8); 6] = [
+    ('β', 0xe1),
+    ('μ', 0xe6),
+    ('Ω', 0xea),
+    ('ϕ', 0xed),
+    ('∈', 0xee),
+    ('∅', 0xed),
+];
+
+pub fn from_char(c: char) -> Option<u8> {
+    match c {
+        // Printable ASCII is the same in code page 437
+        ' '..='~' => Some(c as u8),
+        '⌂' => Some(0x7f),
+        // ASCII control characters are not glyphs
+        '\0'..='\x7f' => None,
+        c => CONTROL
+            .iter()
+            .position(|&glyph| glyph == c)
+            .or_else(|| UPPER.iter().position(|&glyph| glyph == c).map(|i| i + 0x80))
+            .map(|i| i as u8)
+            .or_else(|| {
+                ALIASES
+                    .iter()
+                    .find(|&&(alias, _)| alias == c)
+                    .map(|&(_, byte)| byte)
+            }),
+    }
+}
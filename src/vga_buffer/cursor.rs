@@ -0,0 +1,44 @@
+/*
+ * The blinking cursor is not part of the text buffer at all, it is drawn by
+ * the CRT controller (CRTC) of the VGA card. The CRTC registers are not
+ * memory mapped, they sit behind an index/data I/O port pair: we first write
+ * the number of the register we want to 0x3D4 and then read or write its
+ * value through 0x3D5 (see registers.rs).
+ */
+use super::registers::{read_crtc, write_crtc};
+
+const CURSOR_START_REGISTER: u8 = 0x0A;
+const CURSOR_END_REGISTER: u8 = 0x0B;
+const CURSOR_LOCATION_HIGH_REGISTER: u8 = 0x0E;
+const CURSOR_LOCATION_LOW_REGISTER: u8 = 0x0F;
+
+// Bit 5 of the cursor start register switches the cursor off
+const CURSOR_DISABLE: u8 = 1 << 5;
+
+/*
+ * The shape of the cursor is given as the first and the last scanline of the
+ * character cell it covers. With the default 16 scanline font, 14..15 is the
+ * usual underline and 0..15 a full block.
+ * Only the low 5 bits are the scanline, the upper bits of both registers
+ * belong to other settings and have to be preserved.
+ */
+pub fn enable(start: u8, end: u8) {
+    let cursor_start = read_crtc(CURSOR_START_REGISTER) & 0xC0;
+    write_crtc(CURSOR_START_REGISTER, cursor_start | (start & 0x1F));
+    let cursor_end = read_crtc(CURSOR_END_REGISTER) & 0xE0;
+    write_crtc(CURSOR_END_REGISTER, cursor_end | (end & 0x1F));
+}
+
+pub fn disable() {
+    let cursor_start = read_crtc(CURSOR_START_REGISTER);
+    write_crtc(CURSOR_START_REGISTER, cursor_start | CURSOR_DISABLE);
+}
+
+/*
+ * The CRTC does not know about rows and columns, the location is the offset
+ * of the character cell from the start of the text buffer.
+ */
+pub fn set_position(offset: u16) {
+    write_crtc(CURSOR_LOCATION_LOW_REGISTER, (offset & 0xFF) as u8);
+    write_crtc(CURSOR_LOCATION_HIGH_REGISTER, (offset >> 8) as u8);
+}
@@ -0,0 +1,405 @@
+/*
+ * Graphics modes: instead of characters the VGA shows pixels, and the video
+ * memory at 0xa0000 holds their colors. We support the two classic ones that
+ * every VGA (and QEMU's standard VGA) has:
+ * - 320x200 with 256 colors (mode 13h), one byte per pixel, row after row.
+ *   The color is an index into the DAC, see palette_entry().
+ * - 640x480 with 16 colors (mode 12h). Every pixel is one bit in each of the
+ *   four planes, the four bits together are the color. Eight pixels share a
+ *   byte, so a pixel can't be written without touching its neighbours.
+ *
+ * enter() takes the screen away from the consoles, they keep on writing into
+ * their shadow buffers in the meantime. Graphics::leave() goes back to the
+ * text mode we came from and puts the active console on the screen again.
+ */
+use super::registers::{self, ModeRegisters};
+use super::{bios_font, program_text_mode, Buffer, ACTIVE_CONSOLE, CONSOLES, PALETTE, TEXT_MODE};
+use x86_64::instructions::interrupts;
+
+const GRAPHICS_MEMORY: usize = 0xa0000;
+
+// Graphics controller registers used for the 16 color mode
+const GRAPHICS_SET_RESET: u8 = 0x00;
+const GRAPHICS_ENABLE_SET_RESET: u8 = 0x01;
+const GRAPHICS_READ_MAP: u8 = 0x04;
+const GRAPHICS_BIT_MASK: u8 = 0x08;
+// Sequencer register that selects the planes a write goes to
+const SEQUENCER_MAP_MASK: u8 = 0x02;
+const PLANES: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsMode {
+    Graphics320x200x256,
+    Graphics640x480x16,
+}
+
+impl GraphicsMode {
+    pub fn width(self) -> usize {
+        match self {
+            GraphicsMode::Graphics320x200x256 => 320,
+            GraphicsMode::Graphics640x480x16 => 640,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            GraphicsMode::Graphics320x200x256 => 200,
+            GraphicsMode::Graphics640x480x16 => 480,
+        }
+    }
+
+    pub fn colors(self) -> usize {
+        match self {
+            GraphicsMode::Graphics320x200x256 => 256,
+            GraphicsMode::Graphics640x480x16 => 16,
+        }
+    }
+
+    fn registers(self) -> &'static ModeRegisters {
+        match self {
+            GraphicsMode::Graphics320x200x256 => &GRAPHICS_320X200X256,
+            GraphicsMode::Graphics640x480x16 => &GRAPHICS_640X480X16,
+        }
+    }
+}
+
+// Register values from Chris Giese's modes.c, just like the text modes
+const GRAPHICS_320X200X256: ModeRegisters = ModeRegisters {
+    misc: 0x63,
+    sequencer: [0x03, 0x01, 0x0F, 0x00, 0x0E],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0xBF, 0x1F, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x9C, 0x0E, 0x8F, 0x28, 0x40, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+    graphics: [0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF],
+    attribute: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F, 0x41, 0x00, 0x0F, 0x00, 0x00,
+    ],
+};
+
+const GRAPHICS_640X480X16: ModeRegisters = ModeRegisters {
+    misc: 0xE3,
+    sequencer: [0x03, 0x01, 0x08, 0x00, 0x06],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0x0B, 0x3E, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0xEA, 0x0C, 0xDF, 0x28, 0x00, 0xE7, 0x04, 0xE3, 0xFF,
+    ],
+    graphics: [0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x05, 0x0F, 0xFF],
+    // Every color is the DAC entry with its own number, like in text mode
+    attribute: [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E,
+        0x0F, 0x01, 0x00, 0x0F, 0x00, 0x00,
+    ],
+};
+
+/*
+ * The palette of the 256 color mode: the 16 text colors as set_palette()
+ * left them, then a 6x6x6 color cube (color 16 + 36 * red + 6 * green +
+ * blue) and a ramp of 24 grays, the same layout as the 256 color xterm
+ * palette. The values are the 6 bits of the DAC.
+ */
+fn palette_entry(palette: &[[u8; 3]; 16], index: u8) -> [u8; 3] {
+    const LEVELS: [u8; 6] = [0, 12, 25, 38, 51, 63];
+    match index {
+        0..=15 => palette[index as usize].map(|level| level >> 2),
+        16..=231 => {
+            let cube = (index - 16) as usize;
+            [LEVELS[cube / 36], LEVELS[cube / 6 % 6], LEVELS[cube % 6]]
+        }
+        _ => {
+            let gray = (2 + (index as usize - 232) * 59 / 23) as u8;
+            [gray, gray, gray]
+        }
+    }
+}
+
+pub struct Graphics {
+    mode: GraphicsMode,
+    // The text mode VGA buffer, the active console gets it back in leave()
+    text_buffer: &'static mut Buffer,
+    // The DAC as it was in text mode
+    saved_dac: [[u8; 3]; 256],
+}
+
+/*
+ * Switch the VGA to a graphics mode. Only one Graphics can exist at a time,
+ * the screen only goes back to text mode through Graphics::leave(). Until
+ * then there is no screen to take and we get None.
+ */
+pub fn enter(mode: GraphicsMode) -> Option<Graphics> {
+    interrupts::without_interrupts(|| {
+        let active = ACTIVE_CONSOLE.lock();
+        // Keeps set_text_mode() away while we switch
+        let _text_mode = TEXT_MODE.lock();
+        let text_buffer = CONSOLES[*active].lock().buffer.detach()?;
+
+        // The graphics modes overwrite the font plane, save the font while we can
+        bios_font();
+        let mut saved_dac = [[0; 3]; 256];
+        for (index, entry) in saved_dac.iter_mut().enumerate() {
+            *entry = registers::read_dac(index as u8);
+        }
+
+        registers::write_mode(mode.registers());
+        let palette = PALETTE.lock();
+        for index in 0..mode.colors() {
+            registers::write_dac(index as u8, palette_entry(&palette, index as u8));
+        }
+        if mode == GraphicsMode::Graphics640x480x16 {
+            /*
+             * Let the graphics controller fill in the color: whatever we write,
+             * every plane gets the bit from the set/reset register and the bit
+             * mask decides which pixels of the byte change.
+             */
+            registers::write_graphics(GRAPHICS_ENABLE_SET_RESET, 0x0F);
+            registers::write_sequencer(SEQUENCER_MAP_MASK, 0x0F);
+        }
+
+        let mut graphics = Graphics {
+            mode,
+            text_buffer,
+            saved_dac,
+        };
+        graphics.clear(0);
+        Some(graphics)
+    })
+}
+
+impl Graphics {
+    pub fn mode(&self) -> GraphicsMode {
+        self.mode
+    }
+
+    pub fn width(&self) -> usize {
+        self.mode.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.mode.height()
+    }
+
+    /*
+     * Change one color of the palette, red, green and blue go from 0 to 255
+     * like for vga_buffer::set_palette(). It only lasts until leave().
+     */
+    pub fn set_palette(&mut self, color: u8, rgb: [u8; 3]) {
+        registers::write_dac(color, rgb.map(|level| level >> 2));
+    }
+
+    fn memory(&self) -> *mut u8 {
+        GRAPHICS_MEMORY as *mut u8
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width() && (y as usize) < self.height()
+    }
+
+    /*
+     * Everything that is drawn is cut off at the edges of the screen, so
+     * shapes may stick out of it.
+     */
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u8) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let (x, y) = (x as usize, y as usize);
+        match self.mode {
+            GraphicsMode::Graphics320x200x256 => unsafe {
+                self.memory()
+                    .add(y * self.width() + x)
+                    .write_volatile(color);
+            },
+            GraphicsMode::Graphics640x480x16 => {
+                let byte = unsafe { self.memory().add((y * self.width() + x) / 8) };
+                registers::write_graphics(GRAPHICS_SET_RESET, color);
+                registers::write_graphics(GRAPHICS_BIT_MASK, 0x80 >> (x % 8));
+                unsafe {
+                    // Reading loads the latches, they keep the other pixels
+                    byte.read_volatile();
+                    byte.write_volatile(0xFF);
+                }
+            }
+        }
+    }
+
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<u8> {
+        if !self.contains(x, y) {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        match self.mode {
+            GraphicsMode::Graphics320x200x256 => {
+                Some(unsafe { self.memory().add(y * self.width() + x).read_volatile() })
+            }
+            GraphicsMode::Graphics640x480x16 => {
+                let byte = unsafe { self.memory().add((y * self.width() + x) / 8) };
+                let mut color = 0;
+                for plane in 0..PLANES {
+                    registers::write_graphics(GRAPHICS_READ_MAP, plane);
+                    if unsafe { byte.read_volatile() } & (0x80 >> (x % 8)) != 0 {
+                        color |= 1 << plane;
+                    }
+                }
+                Some(color)
+            }
+        }
+    }
+
+    pub fn clear(&mut self, color: u8) {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        self.fill_rect(0, 0, width, height, color);
+    }
+
+    // A horizontal line from x0 to x1, in 16 colors 8 pixels at a time
+    fn draw_span(&mut self, x0: i32, x1: i32, y: i32, color: u8) {
+        if y < 0 || y as usize >= self.height() {
+            return;
+        }
+        let x0 = x0.max(0);
+        let x1 = x1.min(self.width() as i32 - 1);
+        if x0 > x1 {
+            return;
+        }
+        let (x0, x1, y) = (x0 as usize, x1 as usize, y as usize);
+        match self.mode {
+            GraphicsMode::Graphics320x200x256 => {
+                for x in x0..=x1 {
+                    unsafe {
+                        self.memory()
+                            .add(y * self.width() + x)
+                            .write_volatile(color)
+                    };
+                }
+            }
+            GraphicsMode::Graphics640x480x16 => {
+                registers::write_graphics(GRAPHICS_SET_RESET, color);
+                let row = y * self.width() / 8;
+                for column in x0 / 8..=x1 / 8 {
+                    let first = x0.max(column * 8) % 8;
+                    let last = x1.min(column * 8 + 7) % 8;
+                    let mask = (0xFFu8 >> first) & (0xFFu8 << (7 - last));
+                    registers::write_graphics(GRAPHICS_BIT_MASK, mask);
+                    unsafe {
+                        let byte = self.memory().add(row + column);
+                        byte.read_volatile();
+                        byte.write_volatile(0xFF);
+                    }
+                }
+            }
+        }
+    }
+
+    // Bresenham's line algorithm, both ends are included
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u8) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.set_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if error2 <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    pub fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u8) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (right, bottom) = (x + width - 1, y + height - 1);
+        self.draw_line(x, y, right, y, color);
+        self.draw_line(x, bottom, right, bottom, color);
+        self.draw_line(x, y, x, bottom, color);
+        self.draw_line(right, y, right, bottom, color);
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u8) {
+        let top = y.max(0);
+        let bottom = (y + height).min(self.height() as i32);
+        for row in top..bottom {
+            self.draw_span(x, x + width - 1, row, color);
+        }
+    }
+
+    /*
+     * The midpoint circle algorithm: we walk along one eighth of the circle
+     * and mirror every point into the other seven.
+     */
+    fn for_each_octant_point<F: FnMut(&mut Graphics, i32, i32)>(&mut self, radius: i32, mut f: F) {
+        let (mut x, mut y) = (radius, 0);
+        let mut error = 1 - radius;
+        while x >= y {
+            f(self, x, y);
+            y += 1;
+            if error < 0 {
+                error += 2 * y + 1;
+            } else {
+                x -= 1;
+                error += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    pub fn draw_circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: u8) {
+        self.for_each_octant_point(radius, |graphics, x, y| {
+            for &(dx, dy) in &[(x, y), (y, x), (-y, x), (-x, y)] {
+                graphics.set_pixel(center_x + dx, center_y + dy, color);
+                graphics.set_pixel(center_x - dx, center_y - dy, color);
+            }
+        });
+    }
+
+    pub fn fill_circle(&mut self, center_x: i32, center_y: i32, radius: i32, color: u8) {
+        self.for_each_octant_point(radius, |graphics, x, y| {
+            graphics.draw_span(center_x - x, center_x + x, center_y + y, color);
+            graphics.draw_span(center_x - x, center_x + x, center_y - y, color);
+            graphics.draw_span(center_x - y, center_x + y, center_y + x, color);
+            graphics.draw_span(center_x - y, center_x + y, center_y - x, color);
+        });
+    }
+
+    /*
+     * Copy an image to the screen with its top left corner at (x, y). The
+     * image is a color for every pixel, row after row, width pixels per row.
+     */
+    pub fn blit(&mut self, x: i32, y: i32, width: usize, pixels: &[u8]) {
+        if width == 0 {
+            return;
+        }
+        for (row, colors) in pixels.chunks(width).enumerate() {
+            for (col, &color) in colors.iter().enumerate() {
+                self.set_pixel(x + col as i32, y + row as i32, color);
+            }
+        }
+    }
+
+    /*
+     * Go back to the text mode we were in before, or the one set_text_mode()
+     * asked for in the meantime, and show the active console again.
+     */
+    pub fn leave(self) {
+        interrupts::without_interrupts(move || {
+            let active = ACTIVE_CONSOLE.lock();
+            let text_mode = TEXT_MODE.lock();
+            for (index, &entry) in self.saved_dac.iter().enumerate() {
+                registers::write_dac(index as u8, entry);
+            }
+            // Also brings back the font the graphics mode wrote over
+            program_text_mode(*text_mode);
+            CONSOLES[*active].lock().activate(self.text_buffer);
+        })
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * The text modes we can switch to. The register values are the well known
+ * ones from Chris Giese's modes.c, the 90 column modes use the 28 MHz pixel
+ * clock and 720 pixels per line instead of the usual 640 for 80 columns.
+ * The 50 and 60 row modes need a font that is only 8 scanlines high, the BIOS
+ * font has 16.
+ */
+use super::registers::ModeRegisters;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMode {
+    Text80x25,
+    Text80x50,
+    Text90x30,
+    Text90x60,
+}
+
+impl TextMode {
+    pub const fn width(self) -> usize {
+        match self {
+            TextMode::Text80x25 | TextMode::Text80x50 => 80,
+            TextMode::Text90x30 | TextMode::Text90x60 => 90,
+        }
+    }
+
+    pub const fn height(self) -> usize {
+        match self {
+            TextMode::Text80x25 => 25,
+            TextMode::Text80x50 => 50,
+            TextMode::Text90x30 => 30,
+            TextMode::Text90x60 => 60,
+        }
+    }
+
+    // Scanlines per character
+    pub const fn font_height(self) -> usize {
+        match self {
+            TextMode::Text80x25 | TextMode::Text90x30 => 16,
+            TextMode::Text80x50 | TextMode::Text90x60 => 8,
+        }
+    }
+
+    pub fn registers(self) -> &'static ModeRegisters {
+        match self {
+            TextMode::Text80x25 => &TEXT_80X25,
+            TextMode::Text80x50 => &TEXT_80X50,
+            TextMode::Text90x30 => &TEXT_90X30,
+            TextMode::Text90x60 => &TEXT_90X60,
+        }
+    }
+}
+
+/*
+ * All text modes use the same graphics controller (odd/even addressing,
+ * memory at 0xb8000) and attribute controller (the 16 colors, 9th column
+ * copies the 8th for the line drawing characters) values.
+ */
+const TEXT_GRAPHICS: [u8; 9] = [0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0E, 0x00, 0xFF];
+const TEXT_ATTRIBUTE: [u8; 21] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F,
+    0x0C, 0x00, 0x0F, 0x08, 0x00,
+];
+
+const TEXT_80X25: ModeRegisters = ModeRegisters {
+    misc: 0x67,
+    sequencer: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x4F, 0x0D, 0x0E, 0x00, 0x00, 0x00,
+        0x50, 0x9C, 0x0E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+    graphics: TEXT_GRAPHICS,
+    attribute: TEXT_ATTRIBUTE,
+};
+
+const TEXT_80X50: ModeRegisters = ModeRegisters {
+    misc: 0x67,
+    sequencer: [0x03, 0x00, 0x03, 0x00, 0x02],
+    crtc: [
+        0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x47, 0x06, 0x07, 0x00, 0x00, 0x01,
+        0x40, 0x9C, 0x8E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+    ],
+    graphics: TEXT_GRAPHICS,
+    attribute: TEXT_ATTRIBUTE,
+};
+
+const TEXT_90X30: ModeRegisters = ModeRegisters {
+    misc: 0xE7,
+    sequencer: [0x03, 0x01, 0x03, 0x00, 0x02],
+    crtc: [
+        0x6B, 0x59, 0x5A, 0x82, 0x60, 0x8D, 0x0B, 0x3E, 0x00, 0x4F, 0x0D, 0x0E, 0x00, 0x00, 0x00,
+        0x00, 0xEA, 0x0C, 0xDF, 0x2D, 0x10, 0xE8, 0x05, 0xA3, 0xFF,
+    ],
+    graphics: TEXT_GRAPHICS,
+    attribute: TEXT_ATTRIBUTE,
+};
+
+const TEXT_90X60: ModeRegisters = ModeRegisters {
+    misc: 0xE7,
+    sequencer: [0x03, 0x01, 0x03, 0x00, 0x02],
+    crtc: [
+        0x6B, 0x59, 0x5A, 0x82, 0x60, 0x8D, 0x0B, 0x3E, 0x00, 0x47, 0x06, 0x07, 0x00, 0x00, 0x00,
+        0x00, 0xEA, 0x0C, 0xDF, 0x2D, 0x08, 0xE8, 0x05, 0xA3, 0xFF,
+    ],
+    graphics: TEXT_GRAPHICS,
+    attribute: TEXT_ATTRIBUTE,
+};
@@ -0,0 +1,180 @@
+/*
+ * A region is a rectangle of the screen that behaves like a small screen of
+ * its own: text wraps at its right edge and when it runs out of rows only the
+ * region scrolls. Everything outside of it stays where it is, so a header or
+ * a status bar can stay put while a log pane below it scrolls.
+ *
+ * The region only remembers where it is and where its cursor is. To write
+ * into it, it is paired with the Writer that owns the screen, see
+ * Writer::region().
+ */
+use super::{cp437, Buffer, Color, ColorCode, ScreenChar, TextSurface, Writer};
+use core::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    top: usize,
+    left: usize,
+    height: usize,
+    width: usize,
+    // Cursor position, relative to the top left corner of the region
+    row_position: usize,
+    column_position: usize,
+    color_code: ColorCode,
+}
+
+impl Region {
+    pub fn new(
+        top: usize,
+        left: usize,
+        height: usize,
+        width: usize,
+        foreground: Color,
+        background: Color,
+    ) -> Region {
+        assert!(height > 0 && width > 0, "empty region");
+        Region {
+            top,
+            left,
+            height,
+            width,
+            row_position: 0,
+            column_position: 0,
+            color_code: ColorCode::new(foreground, background),
+        }
+    }
+
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.color_code = ColorCode::new(foreground, background);
+    }
+
+    /*
+     * The part of the region that is on a width x height screen. The text
+     * mode can change after the region was made, but the region keeps its
+     * place, size and cursor, and whatever is off the screen is simply not
+     * drawn. Once the mode changes back the region is whole again.
+     */
+    fn visible(&self, width: usize, height: usize) -> Bounds {
+        Bounds {
+            top: self.top.min(height),
+            left: self.left.min(width),
+            bottom: (self.top + self.height).min(height),
+            right: (self.left + self.width).min(width),
+        }
+    }
+}
+
+/*
+ * Where a region is drawn right now, in screen rows and columns, see
+ * Region::visible(). Empty if the region is off the screen.
+ */
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    top: usize,
+    left: usize,
+    bottom: usize,
+    right: usize,
+}
+
+impl Bounds {
+    fn contains(&self, row: usize, col: usize) -> bool {
+        (self.top..self.bottom).contains(&row) && (self.left..self.right).contains(&col)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.top == self.bottom || self.left == self.right
+    }
+}
+
+pub struct RegionWriter<'a, S: 'static = Buffer> {
+    writer: &'a mut Writer<S>,
+    region: &'a mut Region,
+    bounds: Bounds,
+}
+
+impl<'a, S: TextSurface> RegionWriter<'a, S> {
+    pub fn new(writer: &'a mut Writer<S>, region: &'a mut Region) -> RegionWriter<'a, S> {
+        // Regions are drawn onto the live screen, not into the scrollback
+        writer.scroll_to_bottom();
+        let bounds = region.visible(writer.width(), writer.height());
+        RegionWriter {
+            writer,
+            region,
+            bounds,
+        }
+    }
+
+    pub fn write_string(&mut self, s: &str) {
+        for c in s.chars() {
+            match c {
+                '\n' => self.new_line(),
+                c => self.write_glyph(cp437::from_char(c).unwrap_or(0xfe)),
+            }
+        }
+    }
+
+    // Off the screen the cursor moves on all the same, only nothing is drawn
+    fn write_glyph(&mut self, byte: u8) {
+        if self.region.column_position >= self.region.width {
+            self.new_line();
+        }
+        let character = ScreenChar {
+            ascii_character: byte,
+            color_code: self.region.color_code,
+        };
+        let row = self.region.top + self.region.row_position;
+        let col = self.region.left + self.region.column_position;
+        if self.bounds.contains(row, col) {
+            self.writer.buffer.write(row, col, character);
+        }
+        self.region.column_position += 1;
+    }
+
+    /*
+     * The same thing Writer::new_line() does for the whole screen, but only
+     * for the rows and columns of the region. Only the part on the screen
+     * scrolls, so its last row is blank afterwards even if the region goes on
+     * below the screen: what would scroll in from there was never drawn.
+     */
+    fn new_line(&mut self) {
+        self.region.column_position = 0;
+        if self.region.row_position < self.region.height - 1 {
+            self.region.row_position += 1;
+            return;
+        }
+        let bounds = self.bounds;
+        if bounds.is_empty() {
+            return;
+        }
+        self.writer
+            .scroll_rows(bounds.top..bounds.bottom, bounds.left..bounds.right);
+        self.clear_row(bounds.bottom - 1 - self.region.top);
+    }
+
+    // Row of the region, as far as it is on the screen
+    fn clear_row(&mut self, row: usize) {
+        let bounds = self.bounds;
+        let row = self.region.top + row;
+        if bounds.is_empty() || !(bounds.top..bounds.bottom).contains(&row) {
+            return;
+        }
+        self.writer
+            .fill_columns(row, bounds.left..bounds.right, self.region.color_code);
+    }
+
+    // Blank the whole region and start over at its top left corner
+    pub fn clear(&mut self) {
+        for row in 0..self.region.height {
+            self.clear_row(row);
+        }
+        self.region.row_position = 0;
+        self.region.column_position = 0;
+    }
+}
+
+impl<S: TextSurface> fmt::Write for RegionWriter<'_, S> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_string(s);
+        Ok(())
+    }
+}
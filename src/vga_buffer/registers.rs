@@ -0,0 +1,274 @@
+/*
+ * The VGA card is made up of a handful of units that each have their own set
+ * of registers, all of them behind I/O ports:
+ * - the miscellaneous output register, which among other things selects the
+ *   pixel clock
+ * - the sequencer, which decides which of the four memory planes get written
+ * - the CRT controller (CRTC), which generates the timings of the picture and
+ *   knows about the cursor
+ * - the graphics controller, which maps the video memory into the address
+ *   space
+ * - the attribute controller, which turns the values in memory into colors
+ * A video mode is nothing more than a value for every one of these registers,
+ * see mode.rs.
+ */
+use x86_64::instructions::port::Port;
+
+const MISC_WRITE_PORT: u16 = 0x3C2;
+const SEQUENCER_ADDRESS_PORT: u16 = 0x3C4;
+const SEQUENCER_DATA_PORT: u16 = 0x3C5;
+const GRAPHICS_ADDRESS_PORT: u16 = 0x3CE;
+const GRAPHICS_DATA_PORT: u16 = 0x3CF;
+const CRTC_ADDRESS_PORT: u16 = 0x3D4;
+const CRTC_DATA_PORT: u16 = 0x3D5;
+/*
+ * The attribute controller has a single port for both the index and the
+ * data, a flip-flop decides which one the next write goes to. Reading the
+ * input status register resets it to "index".
+ */
+const ATTRIBUTE_ADDRESS_PORT: u16 = 0x3C0;
+const ATTRIBUTE_READ_PORT: u16 = 0x3C1;
+const INPUT_STATUS_PORT: u16 = 0x3DA;
+/*
+ * The DAC turns a color index into the voltages for the monitor. Every one of
+ * its 256 entries takes three writes (red, green, blue) of 6 bits each, the
+ * index moves on by itself after the blue one.
+ */
+const DAC_READ_INDEX_PORT: u16 = 0x3C7;
+const DAC_WRITE_INDEX_PORT: u16 = 0x3C8;
+const DAC_DATA_PORT: u16 = 0x3C9;
+
+// Bit 5 of the attribute index, the screen stays blank while it is clear
+const ATTRIBUTE_ENABLE_DISPLAY: u8 = 0x20;
+
+/*
+ * The font lives in plane 2 of the video memory, 32 bytes are reserved for
+ * every one of the 256 glyphs no matter how many scanlines they have.
+ */
+pub const FONT_GLYPHS: usize = 256;
+pub const FONT_GLYPH_STRIDE: usize = 32;
+const FONT_PLANE: u8 = 2;
+
+pub struct ModeRegisters {
+    pub misc: u8,
+    pub sequencer: [u8; 5],
+    pub crtc: [u8; 25],
+    pub graphics: [u8; 9],
+    pub attribute: [u8; 21],
+}
+
+fn read_indexed(address_port: u16, data_port: u16, index: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(address_port);
+    let mut data: Port<u8> = Port::new(data_port);
+    unsafe {
+        address.write(index);
+        data.read()
+    }
+}
+
+fn write_indexed(address_port: u16, data_port: u16, index: u8, value: u8) {
+    let mut address: Port<u8> = Port::new(address_port);
+    let mut data: Port<u8> = Port::new(data_port);
+    unsafe {
+        address.write(index);
+        data.write(value);
+    }
+}
+
+pub fn read_crtc(index: u8) -> u8 {
+    read_indexed(CRTC_ADDRESS_PORT, CRTC_DATA_PORT, index)
+}
+
+pub fn write_crtc(index: u8, value: u8) {
+    write_indexed(CRTC_ADDRESS_PORT, CRTC_DATA_PORT, index, value)
+}
+
+fn read_sequencer(index: u8) -> u8 {
+    read_indexed(SEQUENCER_ADDRESS_PORT, SEQUENCER_DATA_PORT, index)
+}
+
+pub fn write_sequencer(index: u8, value: u8) {
+    write_indexed(SEQUENCER_ADDRESS_PORT, SEQUENCER_DATA_PORT, index, value)
+}
+
+fn read_graphics(index: u8) -> u8 {
+    read_indexed(GRAPHICS_ADDRESS_PORT, GRAPHICS_DATA_PORT, index)
+}
+
+pub fn write_graphics(index: u8, value: u8) {
+    write_indexed(GRAPHICS_ADDRESS_PORT, GRAPHICS_DATA_PORT, index, value)
+}
+
+fn reset_attribute_flip_flop() {
+    let mut input_status: Port<u8> = Port::new(INPUT_STATUS_PORT);
+    unsafe {
+        input_status.read();
+    }
+}
+
+/*
+ * Writing the index of an attribute register also clears the "display
+ * enable" bit, so the screen is blank until enable_display() is called.
+ */
+fn write_attribute(index: u8, value: u8) {
+    let mut address: Port<u8> = Port::new(ATTRIBUTE_ADDRESS_PORT);
+    reset_attribute_flip_flop();
+    unsafe {
+        address.write(index);
+        address.write(value);
+    }
+}
+
+fn enable_display() {
+    let mut address: Port<u8> = Port::new(ATTRIBUTE_ADDRESS_PORT);
+    reset_attribute_flip_flop();
+    unsafe {
+        address.write(ATTRIBUTE_ENABLE_DISPLAY);
+    }
+}
+
+pub fn read_attribute(index: u8) -> u8 {
+    let mut address: Port<u8> = Port::new(ATTRIBUTE_ADDRESS_PORT);
+    let mut data: Port<u8> = Port::new(ATTRIBUTE_READ_PORT);
+    reset_attribute_flip_flop();
+    let value = unsafe {
+        address.write(index);
+        data.read()
+    };
+    enable_display();
+    value
+}
+
+// Change a single attribute register while the screen is on
+pub fn set_attribute(index: u8, value: u8) {
+    write_attribute(index, value);
+    enable_display();
+}
+
+// The [red, green, blue] of a DAC entry, 0 to 63 each
+pub fn read_dac(index: u8) -> [u8; 3] {
+    let mut read_index: Port<u8> = Port::new(DAC_READ_INDEX_PORT);
+    let mut data: Port<u8> = Port::new(DAC_DATA_PORT);
+    unsafe {
+        read_index.write(index);
+        [data.read(), data.read(), data.read()]
+    }
+}
+
+pub fn write_dac(index: u8, [red, green, blue]: [u8; 3]) {
+    let mut write_index: Port<u8> = Port::new(DAC_WRITE_INDEX_PORT);
+    let mut data: Port<u8> = Port::new(DAC_DATA_PORT);
+    unsafe {
+        write_index.write(index);
+        data.write(red);
+        data.write(green);
+        data.write(blue);
+    }
+}
+
+pub fn write_mode(registers: &ModeRegisters) {
+    let mut misc: Port<u8> = Port::new(MISC_WRITE_PORT);
+    unsafe {
+        misc.write(registers.misc);
+    }
+    for (index, &value) in registers.sequencer.iter().enumerate() {
+        write_sequencer(index as u8, value);
+    }
+    /*
+     * CRTC registers 0 to 7 are write protected by bit 7 of register 0x11.
+     * Unlock them and make sure that our values don't lock them again.
+     */
+    write_crtc(0x03, read_crtc(0x03) | 0x80);
+    write_crtc(0x11, read_crtc(0x11) & !0x80);
+    for (index, &value) in registers.crtc.iter().enumerate() {
+        let value = match index {
+            0x03 => value | 0x80,
+            0x11 => value & !0x80,
+            _ => value,
+        };
+        write_crtc(index as u8, value);
+    }
+    for (index, &value) in registers.graphics.iter().enumerate() {
+        write_graphics(index as u8, value);
+    }
+    for (index, &value) in registers.attribute.iter().enumerate() {
+        write_attribute(index as u8, value);
+    }
+    enable_display();
+}
+
+/*
+ * In text mode the planes are interleaved ("odd/even" addressing): even
+ * addresses go to plane 0 (the characters), odd ones to plane 1 (the
+ * attributes) and plane 2 can't be reached at all. To get at the font we
+ * switch to flat addressing of plane 2 for as long as f runs and then put
+ * everything back. Plane 2 then shows up at 0xb8000.
+ */
+fn with_font_plane<F: FnOnce(*mut u8)>(f: F) {
+    let sequencer_map_mask = read_sequencer(0x02);
+    let sequencer_memory_mode = read_sequencer(0x04);
+    let graphics_read_map = read_graphics(0x04);
+    let graphics_mode = read_graphics(0x05);
+    let graphics_misc = read_graphics(0x06);
+
+    write_sequencer(0x04, sequencer_memory_mode | 0x04);
+    write_graphics(0x05, graphics_mode & !0x10);
+    write_graphics(0x06, graphics_misc & !0x02);
+    write_sequencer(0x02, 1 << FONT_PLANE);
+    write_graphics(0x04, FONT_PLANE);
+
+    f(0xb8000 as *mut u8);
+
+    write_sequencer(0x02, sequencer_map_mask);
+    write_sequencer(0x04, sequencer_memory_mode);
+    write_graphics(0x04, graphics_read_map);
+    write_graphics(0x05, graphics_mode);
+    write_graphics(0x06, graphics_misc);
+}
+
+// font holds FONT_GLYPHS glyphs of height bytes each
+pub fn read_font(font: &mut [u8], height: usize) {
+    assert!(font.len() >= FONT_GLYPHS * height && height <= FONT_GLYPH_STRIDE);
+    with_font_plane(|plane| {
+        for (glyph, rows) in font.chunks_exact_mut(height).take(FONT_GLYPHS).enumerate() {
+            for (row, byte) in rows.iter_mut().enumerate() {
+                let offset = glyph * FONT_GLYPH_STRIDE + row;
+                *byte = unsafe { plane.add(offset).read_volatile() };
+            }
+        }
+    });
+}
+
+// The FONT_GLYPH_STRIDE scanlines of one glyph
+pub fn read_glyph(index: usize) -> [u8; FONT_GLYPH_STRIDE] {
+    assert!(index < FONT_GLYPHS);
+    let mut glyph = [0; FONT_GLYPH_STRIDE];
+    with_font_plane(|plane| {
+        for (row, byte) in glyph.iter_mut().enumerate() {
+            let offset = index * FONT_GLYPH_STRIDE + row;
+            *byte = unsafe { plane.add(offset).read_volatile() };
+        }
+    });
+    glyph
+}
+
+/*
+ * Replace the glyphs starting at first, the rest of the font stays as it is.
+ * Every glyph is height bytes, one for each scanline.
+ */
+pub fn write_glyphs(first: usize, glyphs: &[u8], height: usize) {
+    assert!(height > 0 && height <= FONT_GLYPH_STRIDE);
+    with_font_plane(|plane| {
+        let glyphs = glyphs
+            .chunks_exact(height)
+            .take(FONT_GLYPHS.saturating_sub(first));
+        for (glyph, rows) in (first..).zip(glyphs) {
+            for row in 0..FONT_GLYPH_STRIDE {
+                let offset = glyph * FONT_GLYPH_STRIDE + row;
+                // The unused scanlines of the slot are cleared
+                let byte = rows.get(row).copied().unwrap_or(0);
+                unsafe { plane.add(offset).write_volatile(byte) };
+            }
+        }
+    });
+}
@@ -0,0 +1,48 @@
+/*
+ * Rows that scroll off the top of the screen end up here instead of being
+ * lost. It's a ring buffer: once it is full the oldest row gets overwritten.
+ * The rows are kept in normal memory, only the part that is being looked at
+ * gets copied into the VGA buffer.
+ */
+use super::{ScreenChar, MAX_BUFFER_WIDTH};
+
+pub const SCROLLBACK_LINES: usize = 256;
+
+// Rows are as wide as they can get in any text mode
+pub type Row = [ScreenChar; MAX_BUFFER_WIDTH];
+
+pub struct Scrollback {
+    rows: [Row; SCROLLBACK_LINES],
+    // Index of the oldest row
+    start: usize,
+    len: usize,
+}
+
+impl Scrollback {
+    pub const fn new(blank: ScreenChar) -> Scrollback {
+        Scrollback {
+            rows: [[blank; MAX_BUFFER_WIDTH]; SCROLLBACK_LINES],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn push(&mut self, row: Row) {
+        if self.len < SCROLLBACK_LINES {
+            self.rows[(self.start + self.len) % SCROLLBACK_LINES] = row;
+            self.len += 1;
+        } else {
+            self.rows[self.start] = row;
+            self.start = (self.start + 1) % SCROLLBACK_LINES;
+        }
+    }
+
+    // Row 0 is the oldest one we still have
+    pub fn get(&self, index: usize) -> &Row {
+        &self.rows[(self.start + index) % SCROLLBACK_LINES]
+    }
+}
@@ -0,0 +1,110 @@
+/*
+ * Everything a Writer needs from the screen it draws on: a place to put
+ * characters and, if there is one, a cursor. The VGA buffer is one such
+ * surface, MemoryBuffer is another one that only lives in memory. With it the
+ * wrapping, scrolling and escape sequence handling of the Writer can run in
+ * a normal program, e.g. in the unit tests on the host (cargo test-host).
+ *
+ * Characters are numbered like in the VGA buffer: row * width + col, where
+ * width is the width of the text mode.
+ */
+use super::{cursor, Buffer, ScreenChar};
+#[cfg(all(test, not(target_os = "none")))]
+use super::{BLANK, MAX_BUFFER_HEIGHT, MAX_BUFFER_WIDTH};
+
+pub trait TextSurface {
+    fn read(&self, index: usize) -> ScreenChar;
+
+    fn write(&mut self, index: usize, character: ScreenChar);
+
+    // The cursor covers the scanlines start..=end of a character cell
+    fn enable_cursor(&mut self, _start: u8, _end: u8) {}
+
+    fn disable_cursor(&mut self) {}
+
+    fn move_cursor(&mut self, _index: usize) {}
+}
+
+impl TextSurface for Buffer {
+    fn read(&self, index: usize) -> ScreenChar {
+        self.chars[index].read()
+    }
+
+    fn write(&mut self, index: usize, character: ScreenChar) {
+        self.chars[index].write(character);
+    }
+
+    fn enable_cursor(&mut self, start: u8, end: u8) {
+        cursor::enable(start, end);
+    }
+
+    fn disable_cursor(&mut self) {
+        cursor::disable();
+    }
+
+    fn move_cursor(&mut self, index: usize) {
+        cursor::set_position(index as u16);
+    }
+}
+
+// A screen in normal memory, it remembers where the cursor would be. Only
+// the unit tests on the host need it
+#[cfg(all(test, not(target_os = "none")))]
+pub struct MemoryBuffer {
+    chars: [ScreenChar; MAX_BUFFER_WIDTH * MAX_BUFFER_HEIGHT],
+    cursor: Option<usize>,
+    cursor_shape: (u8, u8),
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl MemoryBuffer {
+    pub fn new() -> MemoryBuffer {
+        MemoryBuffer {
+            chars: [BLANK; MAX_BUFFER_WIDTH * MAX_BUFFER_HEIGHT],
+            cursor: None,
+            cursor_shape: (0, 0),
+        }
+    }
+
+    // None while the cursor is switched off
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    pub fn cursor_shape(&self) -> (u8, u8) {
+        self.cursor_shape
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl Default for MemoryBuffer {
+    fn default() -> MemoryBuffer {
+        MemoryBuffer::new()
+    }
+}
+
+#[cfg(all(test, not(target_os = "none")))]
+impl TextSurface for MemoryBuffer {
+    fn read(&self, index: usize) -> ScreenChar {
+        self.chars[index]
+    }
+
+    fn write(&mut self, index: usize, character: ScreenChar) {
+        self.chars[index] = character;
+    }
+
+    fn enable_cursor(&mut self, start: u8, end: u8) {
+        self.cursor_shape = (start, end);
+        self.cursor = Some(self.cursor.unwrap_or(0));
+    }
+
+    fn disable_cursor(&mut self) {
+        self.cursor = None;
+    }
+
+    fn move_cursor(&mut self, index: usize) {
+        if self.cursor.is_some() {
+            self.cursor = Some(index);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+/*
+ * The kernel comes up and can print, with nothing but what _start does
+ * before the tests run.
+ */
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rust_os::{kmsg, println, serial_println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn println_works() {
+    println!("println_works output");
+}
+
+#[test_case]
+fn println_scrolls() {
+    for line in 0..200 {
+        println!("line {}", line);
+    }
+}
+
+#[test_case]
+fn serial_println_works() {
+    serial_println!();
+}
+
+#[test_case]
+fn print_goes_to_kmsg() {
+    println!("for the kmsg ring");
+    let last = kmsg::records().last().expect("kmsg is empty");
+    assert_eq!(last.text(), "for the kmsg ring");
+}
+
+// The memory sink of the logger is kmsg, the screen sink doesn't add a second copy
+#[test_case]
+fn log_goes_to_kmsg_once() {
+    log::info!("for the kmsg ring too");
+    let mut records =
+        kmsg::records().filter(|record| record.text().ends_with("for the kmsg ring too"));
+    assert_eq!(
+        records
+            .next()
+            .map(|record| record.text().starts_with("[ INFO]")),
+        Some(true)
+    );
+    assert!(records.next().is_none());
+}
+
+// The timer interrupt is what fails a test that hangs, so it had better run
+#[test_case]
+fn timer_ticks() {
+    let start = rust_os::interrupts::uptime_ms();
+    while rust_os::interrupts::uptime_ms() == start {
+        x86_64::instructions::hlt();
+    }
+}
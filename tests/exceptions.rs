@@ -0,0 +1,56 @@
+/*
+ * The exceptions we can come back from, see src/interrupts/exceptions.rs.
+ */
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rust_os::testing::snapshot;
+use x86_64::instructions::interrupts;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
+}
+
+#[test_case]
+fn breakpoint_returns() {
+    interrupts::int3();
+}
+
+#[test_case]
+fn breakpoint_returns_twice() {
+    interrupts::int3();
+    interrupts::int3();
+}
+
+// What the handler says ends up on the screen, the addresses are different every build
+#[test_case]
+fn breakpoint_is_reported() {
+    interrupts::int3();
+    snapshot::assert_text(&[
+        "EXCEPTION: BREAKPOINT",
+        "InterruptStackFrame {",
+        "    instruction_pointer: VirtAddr(",
+        "        0x*",
+        "    ),",
+        "    code_segment: 8,",
+        "    cpu_flags: 0x*",
+        "    stack_pointer: VirtAddr(",
+        "        0x*",
+        "    ),",
+        "    stack_segment: *",
+        "}",
+        "",
+    ]);
+}
@@ -0,0 +1,59 @@
+/*
+ * Glyphs drawn with set_glyph() have to outlive the font reload of a text
+ * mode switch.
+ */
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rust_os::vga_buffer::{self, TextMode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
+}
+
+// A box, one scanline for each of the 8 scanlines of the 80x50 mode
+const BOX: [u8; 8] = [0xff, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xff];
+
+fn assert_box(index: u8) {
+    let glyph = vga_buffer::glyph(index).expect("not in text mode");
+    assert_eq!(glyph[..BOX.len()], BOX);
+    // The rest of the character cell is empty
+    assert!(glyph[BOX.len()..].iter().all(|&scanline| scanline == 0));
+}
+
+#[test_case]
+fn glyph_is_drawn() {
+    vga_buffer::set_glyph(0x01, &BOX);
+    assert_box(0x01);
+}
+
+#[test_case]
+fn glyph_survives_mode_switches() {
+    vga_buffer::set_glyph(0x02, &BOX);
+    vga_buffer::set_text_mode(TextMode::Text80x50);
+    assert_box(0x02);
+    vga_buffer::set_text_mode(TextMode::Text80x25);
+    assert_box(0x02);
+}
+
+#[test_case]
+fn reset_font_forgets_glyphs() {
+    vga_buffer::set_glyph(0x03, &BOX);
+    vga_buffer::reset_font();
+    assert_ne!(
+        vga_buffer::glyph(0x03).expect("not in text mode")[..BOX.len()],
+        BOX
+    );
+}
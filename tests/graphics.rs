@@ -0,0 +1,91 @@
+/*
+ * The graphics modes on QEMU's standard VGA: pixels that are drawn can be
+ * read back, and leaving puts the text mode and the console back.
+ */
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rust_os::println;
+use rust_os::testing::snapshot;
+use rust_os::vga_buffer::graphics::{self, Graphics, GraphicsMode};
+use rust_os::vga_buffer::{self, TextMode};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
+}
+
+// Pixels in the corners, at the edges of a byte in mode 12h and in between
+fn draw_and_read_back(graphics: &mut Graphics) {
+    let (right, bottom) = (graphics.width() as i32 - 1, graphics.height() as i32 - 1);
+    let colors = graphics.mode().colors() as u32;
+    let pixels = [
+        (0, 0),
+        (7, 0),
+        (8, 1),
+        (right, 0),
+        (0, bottom),
+        (right, bottom),
+        (100, 50),
+    ];
+    for (index, &(x, y)) in pixels.iter().enumerate() {
+        let color = ((index as u32 * 5 + 1) % colors) as u8;
+        graphics.set_pixel(x, y, color);
+        assert_eq!(graphics.get_pixel(x, y), Some(color));
+    }
+    // The neighbours in the same byte are left alone
+    assert_eq!(graphics.get_pixel(1, 0), Some(0));
+    assert_eq!(graphics.get_pixel(-1, 0), None);
+    assert_eq!(graphics.get_pixel(right + 1, 0), None);
+}
+
+fn round_trip(mode: GraphicsMode) {
+    println!("before {:?}", mode);
+    let mut graphics = graphics::enter(mode).expect("the screen is taken");
+    assert_eq!((graphics.mode(), graphics.width()), (mode, mode.width()));
+    draw_and_read_back(&mut graphics);
+    // Printing still works, it shows up once we are back in text mode
+    println!("during");
+    graphics.leave();
+
+    assert_eq!(vga_buffer::text_mode(), TextMode::Text80x25);
+    match mode {
+        GraphicsMode::Graphics320x200x256 => {
+            snapshot::assert_text(&["before Graphics320x200x256", "during", ""])
+        }
+        GraphicsMode::Graphics640x480x16 => {
+            snapshot::assert_text(&["before Graphics640x480x16", "during", ""])
+        }
+    }
+}
+
+#[test_case]
+fn mode_13h_round_trip() {
+    round_trip(GraphicsMode::Graphics320x200x256);
+}
+
+#[test_case]
+fn mode_12h_round_trip() {
+    round_trip(GraphicsMode::Graphics640x480x16);
+}
+
+#[test_case]
+fn only_one_graphics_at_a_time() {
+    let graphics = graphics::enter(GraphicsMode::Graphics320x200x256).expect("the screen is taken");
+    assert!(graphics::enter(GraphicsMode::Graphics640x480x16).is_none());
+    graphics.leave();
+    graphics::enter(GraphicsMode::Graphics640x480x16)
+        .expect("leave() gives the screen back")
+        .leave();
+}
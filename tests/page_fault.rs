@@ -0,0 +1,28 @@
+/*
+ * A page fault can't be returned from, its handler has to panic instead of
+ * faulting again and again. It passes when it does, see Cargo.toml, and the
+ * panic has to be the handler's own.
+ */
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use rust_os::testing;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    testing::should_panic_with(page_fault_panics, "EXCEPTION: PAGE FAULT")
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::should_panic_handler(info)
+}
+
+fn page_fault_panics() {
+    // Far away from anything the bootloader mapped
+    unsafe {
+        core::ptr::write_volatile(0x4444_4444_0000 as *mut u64, 42);
+    }
+}
@@ -0,0 +1,25 @@
+/*
+ * A failing assertion has to panic, and the test passes when it does. That
+ * needs a panic handler that counts a panic as success, which is why it is a
+ * test binary of its own, without the test harness (see Cargo.toml).
+ */
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use rust_os::testing;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    testing::should_panic(failed_assertion_panics)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::should_panic_handler(info)
+}
+
+fn failed_assertion_panics() {
+    assert_eq!(0, 1);
+}
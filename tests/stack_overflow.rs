@@ -0,0 +1,37 @@
+/*
+ * A kernel stack overflow has to end up in the double fault handler, on the
+ * stack it has in the TSS, instead of resetting the machine. The handler is
+ * the kernel's own, which reports the fault and panics from that stack, so
+ * the test passes when it gets to the panic handler with the handler's
+ * message (see Cargo.toml). init() has built everything that stack needs.
+ */
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use rust_os::testing;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    testing::should_panic_with(stack_overflow_double_faults, "EXCEPTION: DOUBLE FAULT")
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::should_panic_handler(info)
+}
+
+fn stack_overflow_double_faults() {
+    stack_overflow();
+}
+
+#[allow(unconditional_recursion)]
+fn stack_overflow() {
+    stack_overflow();
+    // Keeps the recursion from turning into a loop
+    let x = 0;
+    unsafe {
+        core::ptr::read_volatile(&x);
+    }
+}
@@ -0,0 +1,31 @@
+/*
+ * A test that hangs has to be failed by the timer interrupt, otherwise
+ * cargo test waits for bootimage's test-timeout and never learns which test
+ * it was. Here hanging is what the test is supposed to do, so the timeout
+ * counts as success (see testing::should_time_out()). It is a test binary
+ * of its own, without the test harness (see Cargo.toml).
+ */
+#![no_std]
+#![no_main]
+
+use core::panic::PanicInfo;
+use rust_os::testing;
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    // A few timer interrupts, not the 10 seconds a normal test gets
+    testing::set_test_timeout(200);
+    testing::should_time_out(endless_loop_times_out)
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::test_panic_handler(info)
+}
+
+fn endless_loop_times_out() {
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * What println! and friends leave on the screen, compared to golden
+ * snapshots, see src/testing/snapshot.rs.
+ */
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(rust_os::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use core::panic::PanicInfo;
+use rust_os::testing::snapshot;
+use rust_os::vga_buffer::Color;
+use rust_os::{print, print_colored, println};
+
+#[no_mangle]
+pub extern "C" fn _start() -> ! {
+    rust_os::init();
+    test_main();
+    unreachable!("the test runner exits QEMU");
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    rust_os::testing::test_panic_handler(info)
+}
+
+// A full row of the 80x25 text mode
+const FULL_ROW: &str = concat!(
+    "0123456789",
+    "0123456789",
+    "0123456789",
+    "0123456789",
+    "0123456789",
+    "0123456789",
+    "0123456789",
+    "0123456789",
+);
+
+#[test_case]
+fn println_wraps_at_column_80() {
+    println!("{}wrapped", FULL_ROW);
+    snapshot::assert_text(&[FULL_ROW, "wrapped", ""]);
+}
+
+#[test_case]
+fn full_row_waits_for_more() {
+    print!("{}", FULL_ROW);
+    snapshot::assert_text(&[FULL_ROW]);
+    println!();
+    snapshot::assert_text(&[FULL_ROW, ""]);
+}
+
+#[test_case]
+fn new_line_scrolls_up() {
+    println!("first");
+    println!("second");
+    snapshot::assert_text(&["first", "second", ""]);
+    println!();
+    snapshot::assert_text(&["first", "second", "", ""]);
+}
+
+#[test_case]
+fn scrolling_drops_the_top_row() {
+    for letter in b'a'..=b'y' {
+        println!("{}", letter as char);
+    }
+    // 25 lines and the empty one after them, "a" is gone
+    snapshot::assert_text(&[
+        "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s",
+        "t", "u", "v", "w", "x", "y", "",
+    ]);
+}
+
+#[test_case]
+fn print_colored_sets_the_colors() {
+    print_colored!(Color::LightRed, Color::Blue, "red");
+    println!(" yellow");
+    snapshot::assert_text(&["red yellow", ""]);
+    snapshot::assert_foreground(&["ccceeeeeee", ""]);
+    snapshot::assert_background(&["1110000000", ""]);
+}